@@ -0,0 +1,26 @@
+-- Key solves by puzzle type as well as date, and record where each row came
+-- from so that manual entries can be protected from syncs. Manually recorded
+-- solves can carry notes, which live in their own table.
+
+CREATE TABLE xwords_new(
+    date DATE NOT NULL,
+    puzzle_type TEXT NOT NULL DEFAULT 'daily',
+    solved BOOLEAN NOT NULL,
+    duration INTEGER,
+    source TEXT NOT NULL DEFAULT 'api',
+    PRIMARY KEY (date, puzzle_type)
+);
+
+INSERT INTO xwords_new(date, solved, duration) SELECT date, solved, duration FROM xwords;
+DROP TABLE xwords;
+ALTER TABLE xwords_new RENAME TO xwords;
+
+CREATE TABLE notes(
+    id INTEGER PRIMARY KEY,
+    date DATE NOT NULL,
+    puzzle_type TEXT NOT NULL DEFAULT 'daily',
+    text TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX notes_date ON notes(date, puzzle_type);
@@ -0,0 +1,8 @@
+-- Free-form tags attached to a puzzle.
+
+CREATE TABLE tags(
+    date DATE NOT NULL,
+    puzzle_type TEXT NOT NULL DEFAULT 'daily',
+    tag TEXT NOT NULL,
+    PRIMARY KEY (date, puzzle_type, tag)
+);
@@ -0,0 +1,23 @@
+-- Metadata for each puzzle fetched from the NYTimes puzzle endpoint.
+
+CREATE TABLE puzzles(
+    puzzle_id INTEGER NOT NULL PRIMARY KEY,
+    date DATE NOT NULL,
+    puzzle_type TEXT NOT NULL DEFAULT 'daily',
+    title TEXT,
+    editor TEXT,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    rebus BOOLEAN NOT NULL DEFAULT 0,
+    notes TEXT,
+    UNIQUE (date, puzzle_type)
+);
+
+CREATE TABLE puzzle_constructors(
+    puzzle_id INTEGER NOT NULL REFERENCES puzzles(puzzle_id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (puzzle_id, position)
+);
+
+CREATE INDEX puzzle_constructors_name ON puzzle_constructors(name);
@@ -0,0 +1,27 @@
+-- Clues and answers for each puzzle, with a full-text index over both.
+
+CREATE TABLE clues(
+    puzzle_id INTEGER NOT NULL REFERENCES puzzles(puzzle_id) ON DELETE CASCADE,
+    direction TEXT NOT NULL,
+    label TEXT NOT NULL,
+    text TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    -- Comma-separated indices of the clue's cells in the row-major grid.
+    cells TEXT NOT NULL,
+    PRIMARY KEY (puzzle_id, direction, label)
+);
+
+CREATE VIRTUAL TABLE clue_search USING fts5(text, answer, content = 'clues', content_rowid = 'rowid');
+
+CREATE TRIGGER clues_insert AFTER INSERT ON clues BEGIN
+    INSERT INTO clue_search(rowid, text, answer) VALUES (new.rowid, new.text, new.answer);
+END;
+
+CREATE TRIGGER clues_delete AFTER DELETE ON clues BEGIN
+    INSERT INTO clue_search(clue_search, rowid, text, answer) VALUES ('delete', old.rowid, old.text, old.answer);
+END;
+
+CREATE TRIGGER clues_update AFTER UPDATE ON clues BEGIN
+    INSERT INTO clue_search(clue_search, rowid, text, answer) VALUES ('delete', old.rowid, old.text, old.answer);
+    INSERT INTO clue_search(rowid, text, answer) VALUES (new.rowid, new.text, new.answer);
+END;
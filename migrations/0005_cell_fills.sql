@@ -0,0 +1,10 @@
+-- When each cell of a solved puzzle was filled, from the game's board.
+
+CREATE TABLE cell_fills(
+    puzzle_id INTEGER NOT NULL,
+    cell INTEGER NOT NULL,
+    guess TEXT NOT NULL,
+    -- Seconds into the solve at which the cell was last changed.
+    timestamp INTEGER NOT NULL,
+    PRIMARY KEY (puzzle_id, cell)
+);
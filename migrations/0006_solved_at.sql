@@ -0,0 +1,5 @@
+-- When the NYTimes says a puzzle was first solved, as a unix timestamp. NULL
+-- for unsolved puzzles, manual and imported solves, and solves synced before
+-- this was tracked.
+
+ALTER TABLE xwords ADD COLUMN solved_at INTEGER;
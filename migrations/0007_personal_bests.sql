@@ -0,0 +1,13 @@
+-- Every solve that beat the previous best gold time for its weekday and
+-- puzzle type, recomputed from xwords whenever solves change.
+
+CREATE TABLE personal_bests(
+    date DATE NOT NULL,
+    puzzle_type TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    -- The best this beat, NULL for the first gold solve of its weekday.
+    previous INTEGER,
+    -- When the best was set, as a unix timestamp, if the solve time is known.
+    solved_at INTEGER,
+    PRIMARY KEY (date, puzzle_type)
+);
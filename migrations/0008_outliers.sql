@@ -0,0 +1,13 @@
+-- Gold times that don't reflect real solving, e.g. a puzzle left open
+-- overnight. Detected rows are replaced each time detection runs; manual
+-- rows, set from the CLI, are kept and win over detection.
+
+CREATE TABLE outliers(
+    date DATE NOT NULL,
+    puzzle_type TEXT NOT NULL,
+    -- 0 for a manual override keeping a solve that detection would flag.
+    flagged BOOLEAN NOT NULL,
+    reason TEXT NOT NULL,
+    manual BOOLEAN NOT NULL DEFAULT 0,
+    PRIMARY KEY (date, puzzle_type)
+);
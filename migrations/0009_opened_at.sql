@@ -0,0 +1,4 @@
+-- When the NYTimes says a puzzle was first opened, as a unix timestamp. Only
+-- known for solves synced since this was tracked; `sync --since` backfills it.
+
+ALTER TABLE xwords ADD COLUMN opened_at INTEGER;
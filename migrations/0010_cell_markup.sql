@@ -0,0 +1,13 @@
+-- Circled and shaded squares of each puzzle, which can't be rebuilt from the
+-- clues. Only marked squares have rows. Puzzles stored before markup was
+-- tracked aren't marked as saved, so fetch-puzzles fetches them again.
+
+CREATE TABLE cell_markup(
+    puzzle_id INTEGER NOT NULL REFERENCES puzzles(puzzle_id) ON DELETE CASCADE,
+    cell INTEGER NOT NULL,
+    circled BOOLEAN NOT NULL DEFAULT 0,
+    shaded BOOLEAN NOT NULL DEFAULT 0,
+    PRIMARY KEY (puzzle_id, cell)
+);
+
+ALTER TABLE puzzles ADD COLUMN markup_saved BOOLEAN NOT NULL DEFAULT 0;
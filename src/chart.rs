@@ -0,0 +1,349 @@
+use crate::metric::{Metric, Series, Unit};
+use crate::report::format_relative;
+use crate::stats::{box_summary, kernel_density, percentile, silverman_bandwidth, Completion, SolveClock};
+use crate::util::{date_to_string, WEEKDAYS};
+
+use chrono::prelude::*;
+use plotters::prelude::*;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+static COLORS: [RGBColor; 7] = [
+    RED,
+    MAGENTA,
+    RGBColor(255, 128, 0), // Orange
+    RGBColor(0, 128, 0), // Green
+    RGBColor(0, 128, 255), // Cyan
+    BLUE,
+    BLACK
+];
+
+/// A line color for the `order`th group; weekdays count from Monday.
+pub fn color(order: i32) -> RGBColor {
+    let RGBColor(r, g, b) = &COLORS[order.rem_euclid(COLORS.len() as i32) as usize];
+    RGBColor(*r, *g, *b)
+}
+
+/// Solid gray fill; avoids plotters' unaligned fast path.
+pub fn shade(level: u8) -> ShapeStyle {
+    RGBColor(level, level, level).filled()
+}
+
+/// plotters 0.2 only draws date axes over chrono's `Date`, which chrono has
+/// since deprecated in favour of `NaiveDate`; dates become `Date` only here.
+#[allow(deprecated)]
+pub fn axis_date(date: NaiveDate) -> Date<Utc> {
+    Date::from_utc(date, Utc)
+}
+
+#[allow(deprecated)]
+pub fn axis_date_label(date: &Date<Utc>) -> String {
+    date_to_string(&date.naive_utc())
+}
+
+/// Draws a line for each group of `series`, labelled with its latest value,
+/// over a fainter, thicker one for each of `trends`. The raw values in
+/// `flagged` are marked with crosses, pinned to the top if they're off it.
+pub fn plot_metric(metric: &dyn Metric, series: &Series, trends: &Series, flagged: &Series, path: &Path) {
+    let dates = series.values().chain(trends.values()).chain(flagged.values()).flatten().map(|(date, _)| *date);
+    let (start, end) = match (dates.clone().min(), dates.max()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return
+    };
+    let unit = metric.unit();
+    let highest = series.values().flatten().map(|(_, value)| value / unit.scale()).fold(0.0, f64::max);
+    let top = match unit {
+        Unit::Duration => highest.ceil() + 1.0,
+        Unit::Rate => 1.0
+    };
+
+    let root = BitMapBackend::new(path, (1024, 768)).into_drawing_area();
+    root.fill(&WHITE).expect("Failed to fill.");
+    let mut chart = ChartBuilder::on(&root)
+        .margin(10)
+        .caption(metric.title(), ("sans-serif", 40))
+        .set_label_area_size(LabelAreaPosition::Left, 60)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_ranged(axis_date(start)..axis_date(end + chrono::Duration::days(1)), 0.0..top)
+        .expect("Failed to draw.");
+    chart.configure_mesh()
+        .x_label_formatter(&axis_date_label)
+        .y_label_formatter(&|figure| unit.axis_label(*figure))
+        .draw()
+        .expect("Failed to configure mesh.");
+
+    for (group, points) in trends {
+        chart.draw_series(LineSeries::new(
+            points.iter().map(|(date, value)| (axis_date(*date), value / unit.scale())),
+            color(group.order).mix(0.4).stroke_width(3)
+        )).expect("Failed to plot.");
+    }
+
+    for (group, points) in flagged {
+        chart.draw_series(points.iter().map(|(date, value)| {
+            Cross::new((axis_date(*date), (value / unit.scale()).min(top)), 4, color(group.order).stroke_width(2))
+        })).expect("Failed to plot.");
+    }
+
+    for (group, points) in series {
+        let (last_date, last) = match points.last() {
+            Some(last) => *last,
+            None => continue
+        };
+        let color = color(group.order);
+        chart.draw_series(LineSeries::new(
+            points.iter().map(|(date, value)| (axis_date(*date), value / unit.scale())),
+            &color
+        )).expect("Failed to plot.");
+
+        chart.draw_series(PointSeries::of_element(
+            vec![(axis_date(last_date), last / unit.scale())].into_iter(),
+            1,
+            ShapeStyle::from(&color).filled(),
+            &|coord, size, style| {
+                EmptyElement::at(coord)
+                    + Circle::new((0, 0), size, style)
+                    + Text::new(format!("{}: {}", group.label, unit.format(last)), (5, -5), ("sans-serif", 20).into_font())
+            },
+        )).expect("Failed to draw labels.");
+    }
+}
+
+static BINS: usize = 30;
+
+/// A histogram of gold times for each weekday, keyed from Monday, with a
+/// kernel density estimate drawn over it. The axis stops at half again the
+/// 95th percentile so one overnight solve doesn't squash the rest.
+pub fn plot_distributions(times: &BTreeMap<u32, Vec<f64>>, path: &Path) {
+    let root = BitMapBackend::new(path, (1600, 900)).into_drawing_area();
+    root.fill(&WHITE).expect("Failed to fill.");
+    let root = root.titled("Gold Time Distributions by Weekday", ("sans-serif", 40)).expect("Failed to draw.");
+
+    let areas = root.split_evenly((2, 4));
+    for (day, values) in times {
+        let area = &areas[*day as usize];
+        let top = match percentile(values, 95) {
+            Some(p95) => (p95 * 1.5 / 60.0).ceil().max(1.0),
+            None => continue
+        };
+        let width = top / BINS as f64;
+        let mut counts = vec![0u32; BINS];
+        for value in values {
+            if let Some(count) = counts.get_mut((value / 60.0 / width) as usize) {
+                *count += 1;
+            }
+        }
+        let shown = counts.iter().sum::<u32>() as usize;
+        let tallest = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+
+        let mut caption = format!("{} ({} solves", WEEKDAYS[*day as usize], values.len());
+        if shown < values.len() {
+            caption += &format!(", {} off the chart", values.len() - shown);
+        }
+        caption += ")";
+        let mut chart = ChartBuilder::on(area)
+            .margin(10)
+            .caption(caption, ("sans-serif", 20))
+            .set_label_area_size(LabelAreaPosition::Left, 40)
+            .set_label_area_size(LabelAreaPosition::Bottom, 30)
+            .build_ranged(0.0..top, 0.0..tallest * 1.1)
+            .expect("Failed to draw.");
+        chart.configure_mesh()
+            .disable_x_mesh()
+            .x_label_formatter(&|minutes| format!("{}:00", *minutes as u32))
+            .y_label_formatter(&|count| format!("{}", *count as u32))
+            .draw()
+            .expect("Failed to configure mesh.");
+
+        chart.draw_series(counts.iter().enumerate().map(|(i, count)| {
+            Rectangle::new([(i as f64 * width, 0.0), ((i + 1) as f64 * width, *count as f64)], shade(200))
+        })).expect("Failed to plot.");
+
+        // Scaled from a density to solves per bin so it sits on the bars.
+        if let Some(bandwidth) = silverman_bandwidth(values) {
+            let scale = values.len() as f64 * width * 60.0;
+            let curve = (0..=200).map(|i| {
+                let minutes = top * i as f64 / 200.0;
+                (minutes, kernel_density(values, bandwidth, minutes * 60.0) * scale)
+            });
+            chart.draw_series(LineSeries::new(curve, color(*day as i32).stroke_width(2))).expect("Failed to plot.");
+        }
+    }
+}
+
+/// A box plot of gold times for each year, one panel per weekday keyed from
+/// Monday. Outliers above the axis are counted rather than drawn.
+pub fn plot_box_plots(times: &BTreeMap<u32, BTreeMap<i32, Vec<f64>>>, path: &Path) {
+    let root = BitMapBackend::new(path, (1600, 900)).into_drawing_area();
+    root.fill(&WHITE).expect("Failed to fill.");
+    let root = root.titled("Gold Times by Weekday and Year", ("sans-serif", 40)).expect("Failed to draw.");
+
+    let areas = root.split_evenly((2, 4));
+    for (day, years) in times {
+        let area = &areas[*day as usize];
+        let boxes = years.iter()
+            .filter_map(|(year, values)| box_summary(values).map(|summary| (*year as f64, summary)))
+            .collect::<Vec<_>>();
+        let (first, last) = match (boxes.first(), boxes.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => continue
+        };
+        let top = (boxes.iter().map(|(_, b)| b.high).fold(0.0, f64::max) * 1.25 / 60.0).ceil().max(1.0);
+        let color = color(*day as i32);
+
+        let mut chart = ChartBuilder::on(area)
+            .margin(10)
+            .caption(WEEKDAYS[*day as usize].to_string(), ("sans-serif", 20))
+            .set_label_area_size(LabelAreaPosition::Left, 40)
+            .set_label_area_size(LabelAreaPosition::Bottom, 30)
+            .build_ranged(first - 0.5..last + 0.5, 0.0..top)
+            .expect("Failed to draw.");
+        chart.configure_mesh()
+            .disable_x_mesh()
+            .x_labels(boxes.len())
+            .x_label_formatter(&|year| format!("{}", year.round() as i32))
+            .y_label_formatter(&|minutes| format!("{}:00", *minutes as u32))
+            .draw()
+            .expect("Failed to configure mesh.");
+
+        for (x, summary) in &boxes {
+            let minutes = |seconds: f64| seconds / 60.0;
+            let (left, right) = (x - 0.3, x + 0.3);
+            chart.draw_series(vec![
+                PathElement::new(vec![(*x, minutes(summary.low)), (*x, minutes(summary.q1))], &color),
+                PathElement::new(vec![(*x, minutes(summary.q3)), (*x, minutes(summary.high))], &color),
+                PathElement::new(vec![(x - 0.1, minutes(summary.low)), (x + 0.1, minutes(summary.low))], &color),
+                PathElement::new(vec![(x - 0.1, minutes(summary.high)), (x + 0.1, minutes(summary.high))], &color),
+                PathElement::new(vec![
+                    (left, minutes(summary.q1)), (right, minutes(summary.q1)), (right, minutes(summary.q3)),
+                    (left, minutes(summary.q3)), (left, minutes(summary.q1))
+                ], &color),
+                PathElement::new(vec![(left, minutes(summary.median)), (right, minutes(summary.median))], color.stroke_width(2))
+            ]).expect("Failed to plot.");
+
+            let (shown, hidden): (Vec<f64>, Vec<f64>) = summary.outliers.iter().map(|v| minutes(*v)).partition(|v| *v <= top);
+            chart.draw_series(shown.into_iter().map(|v| Circle::new((*x, v), 2, &color))).expect("Failed to plot.");
+            if !hidden.is_empty() {
+                chart.draw_series(std::iter::once(
+                    EmptyElement::at((*x, top)) + Text::new(format!("+{}", hidden.len()), (-8, 2), ("sans-serif", 14).into_font())
+                )).expect("Failed to draw labels.");
+            }
+        }
+    }
+}
+
+static DAY_WIDTH: i32 = 3;
+static YEAR_HEIGHT: i32 = 14;
+static YEAR_GAP: i32 = 4;
+
+fn completion_shade(completion: Completion) -> Option<u8> {
+    match completion {
+        Completion::Untouched => None,
+        Completion::Unsolved => Some(215),
+        Completion::Solved => Some(130),
+        Completion::Gold => Some(30)
+    }
+}
+
+/// A strip per publication year with a mark for each day's puzzle, shaded by
+/// how far we got with it.
+pub fn plot_archive(archive: &BTreeMap<NaiveDate, Completion>, path: &Path) {
+    let (first, last) = match (archive.keys().next(), archive.keys().next_back()) {
+        (Some(first), Some(last)) => (first.year(), last.year()),
+        _ => return
+    };
+    let (left, top) = (60, 60);
+    let years = last - first + 1;
+    let size = (
+        (left + 366 * DAY_WIDTH + 20) as u32,
+        (top + years * (YEAR_HEIGHT + YEAR_GAP) + 50) as u32
+    );
+
+    let root = BitMapBackend::new(path, size).into_drawing_area();
+    root.fill(&WHITE).expect("Failed to fill.");
+    root.draw(&Text::new("Archive Coverage", (left, 15), ("sans-serif", 30).into_font())).expect("Failed to draw caption.");
+
+    let row_y = |year: i32| top + (year - first) * (YEAR_HEIGHT + YEAR_GAP);
+    for year in first..=last {
+        let y = row_y(year);
+        root.draw(&Text::new(year.to_string(), (10, y), ("sans-serif", 14).into_font())).expect("Failed to draw label.");
+        let width = NaiveDate::from_ymd_opt(year, 12, 31).expect("Invalid year").ordinal() as i32 * DAY_WIDTH;
+        root.draw(&Rectangle::new([(left, y), (left + width, y + YEAR_HEIGHT)], &RGBColor(230, 230, 230)))
+            .expect("Failed to draw year.");
+    }
+    for (date, completion) in archive {
+        if let Some(level) = completion_shade(*completion) {
+            let x = left + date.ordinal0() as i32 * DAY_WIDTH;
+            let y = row_y(date.year());
+            root.draw(&Rectangle::new([(x, y), (x + DAY_WIDTH, y + YEAR_HEIGHT)], shade(level))).expect("Failed to draw day.");
+        }
+    }
+
+    let legend_y = row_y(last + 1) + 10;
+    let legend = [(Completion::Gold, "gold"), (Completion::Solved, "solved"), (Completion::Unsolved, "unsolved"), (Completion::Untouched, "not started")];
+    for (i, (completion, label)) in legend.iter().enumerate() {
+        let x = left + i as i32 * 140;
+        let corners = [(x, legend_y), (x + 14, legend_y + 14)];
+        match completion_shade(*completion) {
+            Some(level) => root.draw(&Rectangle::new(corners, shade(level))),
+            None => root.draw(&Rectangle::new(corners, &RGBColor(200, 200, 200)))
+        }.expect("Failed to draw legend.");
+        root.draw(&Text::new(*label, (x + 20, legend_y), ("sans-serif", 14).into_font())).expect("Failed to draw legend.");
+    }
+}
+
+static HOUR_WIDTH: i32 = 36;
+static HOUR_HEIGHT: i32 = 32;
+
+/// A heatmap of when solves were finished, by local weekday and hour, shaded
+/// darker the more solves there were, with how each hour's times compare to
+/// the usual for their puzzle along the bottom.
+pub fn plot_solve_clock(clock: &SolveClock, timezone: &str, path: &Path) {
+    let most = match clock.counts.values().max() {
+        Some(most) => *most as f64,
+        None => return
+    };
+    let (left, top) = (60, 90);
+    let size = ((left + 24 * HOUR_WIDTH + 20) as u32, (top + 8 * HOUR_HEIGHT + 50) as u32);
+
+    let root = BitMapBackend::new(path, size).into_drawing_area();
+    root.fill(&WHITE).expect("Failed to fill.");
+    root.draw(&Text::new(format!("Solves by Hour ({})", timezone), (left, 15), ("sans-serif", 30).into_font()))
+        .expect("Failed to draw caption.");
+
+    for hour in 0..24 {
+        let x = left + hour * HOUR_WIDTH;
+        root.draw(&Text::new(format!("{:02}", hour), (x + 10, top - 20), ("sans-serif", 14).into_font())).expect("Failed to draw label.");
+    }
+    for (day, weekday) in WEEKDAYS.iter().enumerate() {
+        let y = top + day as i32 * HOUR_HEIGHT;
+        root.draw(&Text::new(weekday.to_string(), (10, y + 8), ("sans-serif", 14).into_font())).expect("Failed to draw label.");
+        for hour in 0..24 {
+            let x = left + hour * HOUR_WIDTH;
+            let corners = [(x, y), (x + HOUR_WIDTH - 2, y + HOUR_HEIGHT - 2)];
+            let count = clock.counts.get(&(day as u32, hour as u32)).copied().unwrap_or(0);
+            if count == 0 {
+                root.draw(&Rectangle::new(corners, &RGBColor(230, 230, 230))).expect("Failed to draw hour.");
+                continue;
+            }
+            let level = (235.0 - 205.0 * count as f64 / most) as u8;
+            root.draw(&Rectangle::new(corners, shade(level))).expect("Failed to draw hour.");
+            let text = if level < 140 { &WHITE } else { &BLACK };
+            root.draw(&Text::new(count.to_string(), (x + 6, y + 8), ("sans-serif", 13).into_font().color(text)))
+                .expect("Failed to draw count.");
+        }
+    }
+
+    let y = top + 7 * HOUR_HEIGHT + 10;
+    root.draw(&Text::new("pace", (10, y + 8), ("sans-serif", 14).into_font())).expect("Failed to draw label.");
+    for (hour, summary) in &clock.by_hour {
+        if let Some(ratio) = summary.median_relative() {
+            let x = left + *hour as i32 * HOUR_WIDTH;
+            root.draw(&Text::new(format_relative(ratio), (x + 2, y + 8), ("sans-serif", 12).into_font()))
+                .expect("Failed to draw pace.");
+        }
+    }
+    root.draw(&Text::new("pace: median gold time against the usual for the puzzle's weekday; negative is faster",
+        (left, y + HOUR_HEIGHT), ("sans-serif", 13).into_font())).expect("Failed to draw legend.");
+}
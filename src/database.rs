@@ -1,20 +1,61 @@
-use crate::tracker::{SolveState, XwordSummary};
+use crate::puzzle::{AnswerUse, CellFill, Clue, ClueMatch, GridCell, Puzzle, PuzzleGrid};
+use crate::stats::{DetectedOutlier, PersonalBest};
+use crate::tracker::{Note, Outlier, OutlierMap, ParseVariantError, PuzzleType, SolveSource, SolveState, SolveTimeMap, TagMap, TimestampMap, XwordSummary};
 use crate::util::*;
 
 use chrono::prelude::*;
-use rusqlite::{Connection, params};
-use rusqlite::types::Null;
+use log::{debug, info};
+use rusqlite::{Connection, DatabaseName, OpenFlags, ToSql, params};
+use rusqlite::backup::Progress;
 use thiserror::Error;
 
+use std::collections::HashSet;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+
 static LAST_SOLVE: &str = "last_solve";
 
+pub static BACKUP_DIR: &str = "backups";
+
+static INIT: &str = include_str!("../init.sql");
+
+// Applied in order on open; the database's user_version records how many
+// have already run. Only ever append to this list.
+static MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_solve_source.sql"),
+    include_str!("../migrations/0002_tags.sql"),
+    include_str!("../migrations/0003_puzzles.sql"),
+    include_str!("../migrations/0004_clues.sql"),
+    include_str!("../migrations/0005_cell_fills.sql"),
+    include_str!("../migrations/0006_solved_at.sql"),
+    include_str!("../migrations/0007_personal_bests.sql"),
+    include_str!("../migrations/0008_outliers.sql"),
+    include_str!("../migrations/0009_opened_at.sql"),
+    include_str!("../migrations/0010_cell_markup.sql"),
+];
+
+static UPSERT_XWORD: &str = "INSERT INTO xwords(date, puzzle_type, solved, duration, source) VALUES (?, ?, ?, ?, ?)
+    ON CONFLICT(date, puzzle_type) DO UPDATE SET
+        solved = excluded.solved,
+        duration = excluded.duration,
+        source = excluded.source";
+
 #[derive(Error, Debug)]
 pub enum DbError {
     #[error(transparent)]
-    DbError(#[from] rusqlite::Error),
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error(transparent)]
+    Date(#[from] chrono::ParseError),
 
     #[error(transparent)]
-    DateError(#[from] chrono::ParseError)
+    ParseVariant(#[from] ParseVariantError),
+
+    #[error("Database schema version {0} is newer than the latest known version {1}")]
+    SchemaTooNew(usize, usize),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error)
 }
 
 
@@ -25,58 +66,730 @@ pub struct Database {
 impl Database {
 
     pub fn new(filename: &str) -> Result<Self, DbError> {
-        Ok(Database {
+        let mut db = Database {
             conn: Connection::open(filename)?
-        })
+        };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    pub fn schema_version(&self) -> Result<usize, DbError> {
+        schema_version(&self.conn)
+    }
+
+    fn migrate(&mut self) -> Result<(), DbError> {
+        let initialized: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'xwords')", params![], |row| row.get(0))?;
+        self.conn.execute_batch(INIT)?;
+        let version = self.schema_version()?;
+        if version > MIGRATIONS.len() {
+            return Err(DbError::SchemaTooNew(version, MIGRATIONS.len()));
+        }
+        if initialized && version < MIGRATIONS.len() {
+            let path = self.backup(Path::new(BACKUP_DIR), Some("pre-migration"))?;
+            info!("backed up database to {} before migrating", path.display());
+        }
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            info!("applying migration {}", i + 1);
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Copies the database into `dir` using SQLite's online backup API,
+    /// returning the path of the timestamped copy.
+    pub fn backup(&self, dir: &Path, label: Option<&str>) -> Result<PathBuf, DbError> {
+        create_dir_all(dir)?;
+        let mut name = format!("xword-{}", Local::now().format("%Y%m%d-%H%M%S"));
+        if let Some(label) = label {
+            name = format!("{}-{}", name, label);
+        }
+        // Names only go down to the second, so another backup in the same
+        // second is numbered rather than written over the first.
+        let mut path = dir.join(format!("{}.db", name));
+        let mut copy = 1;
+        while path.exists() {
+            copy += 1;
+            path = dir.join(format!("{}-{}.db", name, copy));
+        }
+        self.conn.backup(DatabaseName::Main, &path, None)?;
+        Ok(path)
+    }
+
+    /// Replaces the database with the contents of a backup, after checking
+    /// that the backup's schema is one we know how to read. Older backups are
+    /// migrated forward once restored.
+    pub fn restore(&mut self, path: &Path) -> Result<(), DbError> {
+        let version = schema_version(&Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?)?;
+        if version > MIGRATIONS.len() {
+            return Err(DbError::SchemaTooNew(version, MIGRATIONS.len()));
+        }
+        self.conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        self.migrate()
     }
 
-    pub fn get_last_solve(&self) -> Result<Option<Date<Utc>>, DbError> {
+    pub fn vacuum(&self) -> Result<(), DbError> {
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Runs SQLite's integrity check, returning the problems it found. An
+    /// empty list means the database is healthy.
+    pub fn integrity_check(&self) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let rows = stmt.query_map(params![], |row| row.get::<usize, String>(0))?;
+        let problems = rows.collect::<Result<Vec<_>, _>>()?;
+        Ok(problems.into_iter().filter(|p| p != "ok").collect())
+    }
+
+    pub fn get_last_solve(&self) -> Result<Option<NaiveDate>, DbError> {
         let mut stmt = self.conn.prepare("SELECT v FROM misc WHERE k = ?")?;
-        match stmt.query(params![LAST_SOLVE])?.next()? {
+        let mut rows = stmt.query(params![LAST_SOLVE])?;
+        match rows.next()? {
             Some(row) => {
                 let date = row.get::<usize, String>(0)?;
-                return Ok(Some(string_to_date(&date)));
+                Ok(Some(string_to_date(&date)))
             },
-            None => return Ok(None)
-        };
+            None => Ok(None)
+        }
     }
 
-    pub fn set_last_solve(&self, date: Date<Utc>) -> Result<(), DbError> {
+    pub fn set_last_solve(&self, date: NaiveDate) -> Result<(), DbError> {
         let mut stmt = self.conn.prepare("REPLACE INTO misc VALUES (?, ?)")?;
         let date = date_to_string(&date);
         stmt.execute(params![LAST_SOLVE, date])?;
         Ok(())
     } 
 
-    pub fn save_xwords(&mut self, xwords: &Vec<XwordSummary>) -> Result<(), DbError> {
+    /// Writes the given rows, replacing any existing row for the same puzzle.
+    /// With `keep_manual`, rows entered by hand are left alone.
+    pub fn save_xwords(&mut self, xwords: &[XwordSummary], keep_manual: bool) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+        {
+            let sql = if keep_manual {
+                format!("{} WHERE xwords.source != '{}'", UPSERT_XWORD, SolveSource::Manual)
+            } else {
+                UPSERT_XWORD.to_string()
+            };
+            let mut stmt = tx.prepare(&sql)?;
+            for xword in xwords {
+                let (solved, time) = xword.solve_state.to_solved_and_time();
+                stmt.execute(params![
+                    date_to_string(&xword.print_date), xword.puzzle_type.as_str(), solved, time, xword.source.as_str()
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records when puzzles were first solved.
+    pub fn save_solve_times(&mut self, times: &SolveTimeMap) -> Result<(), DbError> {
+        self.save_timestamps("solved_at", times)
+    }
+
+    pub fn get_solve_times(&self) -> Result<SolveTimeMap, DbError> {
+        self.get_timestamps("solved_at")
+    }
+
+    /// Records when puzzles were first opened.
+    pub fn save_open_times(&mut self, times: &TimestampMap) -> Result<(), DbError> {
+        self.save_timestamps("opened_at", times)
+    }
+
+    pub fn get_open_times(&self) -> Result<TimestampMap, DbError> {
+        self.get_timestamps("opened_at")
+    }
+
+    fn save_timestamps(&mut self, column: &str, times: &TimestampMap) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(&format!("UPDATE xwords SET {} = ? WHERE date = ? AND puzzle_type = ?", column))?;
+            for ((date, puzzle_type), solved_at) in times {
+                stmt.execute(params![solved_at.timestamp(), date_to_string(date), puzzle_type.as_str()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_timestamps(&self, column: &str) -> Result<TimestampMap, DbError> {
+        let mut stmt = self.conn.prepare(&format!("SELECT date, puzzle_type, {0} FROM xwords WHERE {0} IS NOT NULL", column))?;
+        let mut rows = stmt.query(params![])?;
+        let mut times = TimestampMap::new();
+        while let Some(row) = rows.next()? {
+            let date: String = row.get(0)?;
+            let puzzle_type: String = row.get(1)?;
+            if let Some(at) = DateTime::from_timestamp(row.get(2)?, 0) {
+                times.insert((parse_date(&date)?, puzzle_type.parse()?), at);
+            }
+        }
+        Ok(times)
+    }
+
+    /// Replaces the stored personal best progression.
+    pub fn save_personal_bests(&mut self, bests: &[PersonalBest]) -> Result<(), DbError> {
         let tx = self.conn.transaction()?;
         {
-            let mut stmt = tx.prepare("REPLACE INTO xwords VALUES (?, ?, ?)")?;
-            xwords.iter().for_each(|xword| {
-                match xword.solve_state {
-                    SolveState::Unsolved => stmt.execute(params![date_to_string(&xword.print_date), false, Null]),
-                    SolveState::Solved => stmt.execute(params![date_to_string(&xword.print_date), true, Null]),
-                    SolveState::Gold{ time } => stmt.execute(params![date_to_string(&xword.print_date), true, time])
-                }.expect("Failed inserting all xword times");
+            tx.execute("DELETE FROM personal_bests", params![])?;
+            let mut stmt = tx.prepare("INSERT INTO personal_bests(date, puzzle_type, duration, previous, solved_at) VALUES (?, ?, ?, ?, ?)")?;
+            for best in bests {
+                stmt.execute(params![
+                    date_to_string(&best.print_date), best.puzzle_type.as_str(), best.time, best.previous, best.solved_at.map(|t| t.timestamp())
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns the progression in the order it was saved, which is the order
+    /// the bests were set.
+    pub fn get_personal_bests(&self) -> Result<Vec<PersonalBest>, DbError> {
+        let mut stmt = self.conn.prepare("SELECT date, puzzle_type, duration, previous, solved_at FROM personal_bests ORDER BY rowid")?;
+        let mut rows = stmt.query(params![])?;
+        let mut bests = Vec::new();
+        while let Some(row) = rows.next()? {
+            let date: String = row.get(0)?;
+            let puzzle_type: String = row.get(1)?;
+            bests.push(PersonalBest {
+                print_date: parse_date(&date)?,
+                puzzle_type: puzzle_type.parse()?,
+                time: row.get(2)?,
+                previous: row.get(3)?,
+                solved_at: row.get::<_, Option<i64>>(4)?.and_then(|t| DateTime::from_timestamp(t, 0))
             });
         }
+        Ok(bests)
+    }
+
+    /// Records a solve entered by hand, along with any notes about it.
+    pub fn record_xword(&mut self, xword: &XwordSummary, notes: Option<&str>) -> Result<(), DbError> {
+        let (solved, time) = xword.solve_state.to_solved_and_time();
+        let tx = self.conn.transaction()?;
+        tx.execute(UPSERT_XWORD, params![
+            date_to_string(&xword.print_date), xword.puzzle_type.as_str(), solved, time, SolveSource::Manual.as_str()
+        ])?;
+        if let Some(notes) = notes {
+            tx.execute("INSERT INTO notes(date, puzzle_type, text) VALUES (?, ?, ?)", params![
+                date_to_string(&xword.print_date), xword.puzzle_type.as_str(), notes
+            ])?;
+        }
         tx.commit()?;
-        Ok(())    
+        Ok(())
+    }
+
+    pub fn add_note(&self, date: NaiveDate, puzzle_type: PuzzleType, text: &str) -> Result<i64, DbError> {
+        self.conn.execute("INSERT INTO notes(date, puzzle_type, text) VALUES (?, ?, ?)", params![
+            date_to_string(&date), puzzle_type.as_str(), text
+        ])?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn delete_note(&self, id: i64) -> Result<bool, DbError> {
+        Ok(self.conn.execute("DELETE FROM notes WHERE id = ?", params![id])? > 0)
+    }
+
+    /// Returns notes for one date, or for every date if none is given.
+    pub fn get_notes(&self, date: Option<NaiveDate>) -> Result<Vec<Note>, DbError> {
+        let date = date.map(|d| date_to_string(&d));
+        self.query_notes("SELECT id, date, puzzle_type, text, created_at FROM notes
+            WHERE ?1 IS NULL OR date = ?1 ORDER BY date, id", &date)
+    }
+
+    /// Case-insensitive substring search over note text.
+    pub fn search_notes(&self, query: &str) -> Result<Vec<Note>, DbError> {
+        let pattern = format!("%{}%", query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        self.query_notes("SELECT id, date, puzzle_type, text, created_at FROM notes
+            WHERE text LIKE ?1 ESCAPE '\\' ORDER BY date, id", &pattern)
+    }
+
+    fn query_notes(&self, sql: &str, param: &dyn ToSql) -> Result<Vec<Note>, DbError> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query(params![param])?;
+        let mut notes = Vec::new();
+        while let Some(row) = rows.next()? {
+            let date: String = row.get(1)?;
+            let puzzle_type: String = row.get(2)?;
+            notes.push(Note {
+                id: row.get(0)?,
+                print_date: parse_date(&date)?,
+                puzzle_type: puzzle_type.parse()?,
+                text: row.get(3)?,
+                created_at: row.get(4)?
+            });
+        }
+        Ok(notes)
+    }
+
+    pub fn add_tags(&mut self, date: NaiveDate, puzzle_type: PuzzleType, tags: &[String]) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("INSERT OR IGNORE INTO tags(date, puzzle_type, tag) VALUES (?, ?, ?)")?;
+            for tag in tags {
+                stmt.execute(params![date_to_string(&date), puzzle_type.as_str(), tag])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn remove_tags(&mut self, date: NaiveDate, puzzle_type: PuzzleType, tags: &[String]) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("DELETE FROM tags WHERE date = ? AND puzzle_type = ? AND tag = ?")?;
+            for tag in tags {
+                stmt.execute(params![date_to_string(&date), puzzle_type.as_str(), tag])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_tags(&self) -> Result<TagMap, DbError> {
+        let mut stmt = self.conn.prepare("SELECT date, puzzle_type, tag FROM tags ORDER BY date, tag")?;
+        let mut rows = stmt.query(params![])?;
+        let mut tags = TagMap::new();
+        while let Some(row) = rows.next()? {
+            let date: String = row.get(0)?;
+            let puzzle_type: String = row.get(1)?;
+            tags.entry((parse_date(&date)?, puzzle_type.parse()?)).or_default().push(row.get(2)?);
+        }
+        Ok(tags)
+    }
+
+    /// Replaces the detected outliers, leaving manual overrides alone.
+    pub fn save_outliers(&mut self, outliers: &[DetectedOutlier]) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+        {
+            tx.execute("DELETE FROM outliers WHERE NOT manual", params![])?;
+            let mut stmt = tx.prepare("INSERT OR IGNORE INTO outliers(date, puzzle_type, flagged, reason) VALUES (?, ?, 1, ?)")?;
+            for ((date, puzzle_type), reason) in outliers {
+                stmt.execute(params![date_to_string(date), puzzle_type.as_str(), reason])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns false, changing nothing, if there's no solve for the puzzle.
+    pub fn set_outlier(&mut self, date: NaiveDate, puzzle_type: PuzzleType, flagged: bool) -> Result<bool, DbError> {
+        Ok(self.conn.execute("REPLACE INTO outliers(date, puzzle_type, flagged, reason, manual)
+            SELECT date, puzzle_type, ?3, 'manual', 1 FROM xwords WHERE date = ?1 AND puzzle_type = ?2",
+            params![date_to_string(&date), puzzle_type.as_str(), flagged])? > 0)
+    }
+
+    pub fn clear_outlier(&mut self, date: NaiveDate, puzzle_type: PuzzleType) -> Result<(), DbError> {
+        self.conn.execute("DELETE FROM outliers WHERE date = ? AND puzzle_type = ?", params![date_to_string(&date), puzzle_type.as_str()])?;
+        Ok(())
+    }
+
+    pub fn get_outliers(&self) -> Result<OutlierMap, DbError> {
+        let mut stmt = self.conn.prepare("SELECT date, puzzle_type, flagged, reason, manual FROM outliers")?;
+        let mut rows = stmt.query(params![])?;
+        let mut outliers = OutlierMap::new();
+        while let Some(row) = rows.next()? {
+            let date: String = row.get(0)?;
+            let puzzle_type: String = row.get(1)?;
+            outliers.insert((parse_date(&date)?, puzzle_type.parse()?), Outlier {
+                flagged: row.get(2)?,
+                reason: row.get(3)?,
+                manual: row.get(4)?
+            });
+        }
+        Ok(outliers)
     }
 
     pub fn get_xwords(&self) -> Result<Vec<XwordSummary>, DbError> { 
-        println!("getting all xwords...");
-        let mut stmt = self.conn.prepare("SELECT * FROM xwords ORDER BY date")?;
-        let rows = stmt.query_map(params![], |row| {
+        debug!("getting all xwords...");
+        let mut stmt = self.conn.prepare("SELECT date, puzzle_type, solved, duration, source FROM xwords ORDER BY date, puzzle_type")?;
+        let mut rows = stmt.query(params![])?;
+        let mut xwords = Vec::new();
+        while let Some(row) = rows.next()? {
             let date: String = row.get(0)?;
-            let solved: bool = row.get(1)?;
-            let time: Option<u32> = row.get(2)?;
-            Ok(XwordSummary {
-                print_date: string_to_date(&date),
-                solve_state: SolveState::from_solved_and_time(solved, time)
-            })
+            let puzzle_type: String = row.get(1)?;
+            let solved: bool = row.get(2)?;
+            let time: Option<u32> = row.get(3)?;
+            let source: String = row.get(4)?;
+            xwords.push(XwordSummary {
+                print_date: parse_date(&date)?,
+                puzzle_type: puzzle_type.parse()?,
+                solve_state: SolveState::from_solved_and_time(solved, time),
+                source: source.parse()?
+            });
+        }
+        Ok(xwords)
+    }
+
+    pub fn save_puzzles(&mut self, puzzles: &[(Puzzle, PuzzleGrid)]) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("REPLACE INTO puzzles(puzzle_id, date, puzzle_type, title, editor, width, height, rebus, notes, markup_saved)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, 1)")?;
+            let mut delete_constructors = tx.prepare("DELETE FROM puzzle_constructors WHERE puzzle_id = ?")?;
+            let mut insert_constructor = tx.prepare("INSERT INTO puzzle_constructors(puzzle_id, position, name) VALUES (?, ?, ?)")?;
+            let mut delete_clues = tx.prepare("DELETE FROM clues WHERE puzzle_id = ?")?;
+            let mut insert_clue = tx.prepare("INSERT INTO clues(puzzle_id, direction, label, text, answer, cells) VALUES (?, ?, ?, ?, ?, ?)")?;
+            let mut delete_markup = tx.prepare("DELETE FROM cell_markup WHERE puzzle_id = ?")?;
+            let mut insert_markup = tx.prepare("INSERT INTO cell_markup(puzzle_id, cell, circled, shaded) VALUES (?, ?, ?, ?)")?;
+            for (puzzle, grid) in puzzles {
+                stmt.execute(params![
+                    puzzle.puzzle_id, date_to_string(&puzzle.print_date), puzzle.puzzle_type.as_str(), puzzle.title,
+                    puzzle.editor, puzzle.width, puzzle.height, puzzle.has_rebus, puzzle.notes
+                ])?;
+                delete_constructors.execute(params![puzzle.puzzle_id])?;
+                for (position, name) in puzzle.constructors.iter().enumerate() {
+                    insert_constructor.execute(params![puzzle.puzzle_id, position as u32, name])?;
+                }
+                delete_clues.execute(params![puzzle.puzzle_id])?;
+                for clue in &grid.clues {
+                    let cells = clue.cells.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(",");
+                    insert_clue.execute(params![
+                        puzzle.puzzle_id, clue.direction.as_str(), clue.label, clue.text, grid.answer(clue), cells
+                    ])?;
+                }
+                delete_markup.execute(params![puzzle.puzzle_id])?;
+                for (i, cell) in grid.cells.iter().enumerate().filter(|(_, c)| c.circled || c.shaded) {
+                    insert_markup.execute(params![puzzle.puzzle_id, i as u32, cell.circled, cell.shaded])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Ids of puzzles whose metadata, clues, answers and markup are all stored.
+    pub fn get_puzzle_ids(&self) -> Result<HashSet<u32>, DbError> {
+        let mut stmt = self.conn.prepare("SELECT DISTINCT c.puzzle_id FROM clues c JOIN puzzles p ON p.puzzle_id = c.puzzle_id WHERE p.markup_saved")?;
+        let ids = stmt.query_map(params![], |row| row.get(0))?;
+        Ok(ids.collect::<Result<_, _>>()?)
+    }
+
+    pub fn get_puzzle(&self, date: NaiveDate, puzzle_type: PuzzleType) -> Result<Option<Puzzle>, DbError> {
+        Ok(self.query_puzzles("WHERE p.date = ?1 AND p.puzzle_type = ?2",
+            params![date_to_string(&date), puzzle_type.as_str()])?.pop())
+    }
+
+    pub fn get_puzzles(&self) -> Result<Vec<Puzzle>, DbError> {
+        self.query_puzzles("", params![])
+    }
+
+    /// Puzzles we already have everything from the game for: cell fills and
+    /// when the puzzle was opened and solved.
+    pub fn get_synced_game_ids(&self) -> Result<HashSet<u32>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT p.puzzle_id FROM puzzles p
+             JOIN xwords x ON x.date = p.date AND x.puzzle_type = p.puzzle_type
+             WHERE x.opened_at IS NOT NULL AND x.solved_at IS NOT NULL
+             AND p.puzzle_id IN (SELECT puzzle_id FROM cell_fills)")?;
+        let ids = stmt.query_map(params![], |row| row.get(0))?;
+        Ok(ids.collect::<Result<_, _>>()?)
+    }
+
+    /// Puzzles we have cell fill times for.
+    pub fn get_filled_puzzles(&self) -> Result<Vec<Puzzle>, DbError> {
+        self.query_puzzles("WHERE p.puzzle_id IN (SELECT puzzle_id FROM cell_fills)", params![])
+    }
+
+    fn query_puzzles(&self, condition: &str, params: &[&dyn ToSql]) -> Result<Vec<Puzzle>, DbError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT p.puzzle_id, p.date, p.puzzle_type, p.title, p.editor, p.width, p.height, p.rebus, p.notes,
+                (SELECT group_concat(name, char(31)) FROM
+                    (SELECT name FROM puzzle_constructors c WHERE c.puzzle_id = p.puzzle_id ORDER BY position))
+            FROM puzzles p {} ORDER BY p.date, p.puzzle_type", condition))?;
+        let mut rows = stmt.query(params)?;
+        let mut puzzles = Vec::new();
+        while let Some(row) = rows.next()? {
+            let date: String = row.get(1)?;
+            let puzzle_type: String = row.get(2)?;
+            let constructors: Option<String> = row.get(9)?;
+            puzzles.push(Puzzle {
+                puzzle_id: row.get(0)?,
+                print_date: parse_date(&date)?,
+                puzzle_type: puzzle_type.parse()?,
+                title: row.get(3)?,
+                constructors: constructors.map(|c| c.split('\u{1f}').map(str::to_string).collect()).unwrap_or_default(),
+                editor: row.get(4)?,
+                width: row.get(5)?,
+                height: row.get(6)?,
+                has_rebus: row.get(7)?,
+                notes: row.get(8)?
+            });
+        }
+        Ok(puzzles)
+    }
+
+    /// Full-text search over the clues and answers of stored puzzles, newest
+    /// first. `query` is an FTS5 query; `unsolved` includes puzzles we haven't
+    /// solved, whose answers would otherwise be spoiled.
+    pub fn search_clues(&self, query: &str, unsolved: bool, limit: u32) -> Result<Vec<ClueMatch>, DbError> {
+        let mut stmt = self.conn.prepare("SELECT p.date, p.puzzle_type, c.direction, c.label, c.text, c.answer, x.solved, x.duration
+            FROM clue_search s
+            JOIN clues c ON c.rowid = s.rowid
+            JOIN puzzles p ON p.puzzle_id = c.puzzle_id
+            LEFT JOIN xwords x ON x.date = p.date AND x.puzzle_type = p.puzzle_type
+            WHERE clue_search MATCH ?1 AND (?2 OR x.solved)
+            ORDER BY p.date DESC, c.direction, CAST(c.label AS INTEGER)
+            LIMIT ?3")?;
+        let mut rows = stmt.query(params![query, unsolved, limit])?;
+        let mut matches = Vec::new();
+        while let Some(row) = rows.next()? {
+            let date: String = row.get(0)?;
+            let puzzle_type: String = row.get(1)?;
+            let direction: String = row.get(2)?;
+            let solved: Option<bool> = row.get(6)?;
+            matches.push(ClueMatch {
+                print_date: parse_date(&date)?,
+                puzzle_type: puzzle_type.parse()?,
+                direction: direction.parse()?,
+                label: row.get(3)?,
+                text: row.get(4)?,
+                answer: row.get(5)?,
+                solve_state: SolveState::from_solved_and_time(solved.unwrap_or(false), row.get(7)?)
+            });
+        }
+        Ok(matches)
+    }
+
+    /// Rebuilds a stored puzzle's grid from its clues and markup. Rebus
+    /// squares are left blank.
+    pub fn get_puzzle_grid(&self, puzzle: &Puzzle) -> Result<PuzzleGrid, DbError> {
+        let mut stmt = self.conn.prepare("SELECT direction, label, text, answer, cells FROM clues WHERE puzzle_id = ?")?;
+        let mut rows = stmt.query(params![puzzle.puzzle_id])?;
+        let mut cells = vec![GridCell { answer: None, label: None, circled: false, shaded: false }; (puzzle.width * puzzle.height) as usize];
+        let mut clues = Vec::new();
+        while let Some(row) = rows.next()? {
+            let direction: String = row.get(0)?;
+            let answer: String = row.get(3)?;
+            let indices: String = row.get(4)?;
+            let clue = Clue {
+                direction: direction.parse()?,
+                label: row.get(1)?,
+                text: row.get(2)?,
+                cells: indices.split(',').filter_map(|i| i.parse().ok()).filter(|i| *i < cells.len()).collect()
+            };
+            let letters = answer.chars().collect::<Vec<_>>();
+            for (n, i) in clue.cells.iter().enumerate() {
+                let letter = if letters.len() == clue.cells.len() { letters[n].to_string() } else { String::new() };
+                let cell = &mut cells[*i];
+                if cell.answer.as_deref().is_none_or(str::is_empty) {
+                    cell.answer = Some(letter);
+                }
+            }
+            if let Some(first) = clue.cells.first() {
+                cells[*first].label = Some(clue.label.clone());
+            }
+            clues.push(clue);
+        }
+
+        let mut stmt = self.conn.prepare("SELECT cell, circled, shaded FROM cell_markup WHERE puzzle_id = ?")?;
+        let mut rows = stmt.query(params![puzzle.puzzle_id])?;
+        while let Some(row) = rows.next()? {
+            let i: u32 = row.get(0)?;
+            if let Some(cell) = cells.get_mut(i as usize) {
+                cell.circled = row.get(1)?;
+                cell.shaded = row.get(2)?;
+            }
+        }
+        Ok(PuzzleGrid { width: puzzle.width, height: puzzle.height, cells, clues })
+    }
+
+    /// Replaces the stored fill times of each puzzle given.
+    pub fn save_cell_fills(&mut self, fills: &[(u32, Vec<CellFill>)]) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut delete = tx.prepare("DELETE FROM cell_fills WHERE puzzle_id = ?")?;
+            let mut insert = tx.prepare("INSERT INTO cell_fills(puzzle_id, cell, guess, timestamp) VALUES (?, ?, ?, ?)")?;
+            for (puzzle_id, cells) in fills {
+                delete.execute(params![puzzle_id])?;
+                for fill in cells {
+                    insert.execute(params![puzzle_id, fill.cell as u32, fill.guess, fill.timestamp])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// When each cell of a puzzle was filled, in the order they were filled.
+    pub fn get_cell_fills(&self, puzzle_id: u32) -> Result<Vec<CellFill>, DbError> {
+        let mut stmt = self.conn.prepare("SELECT cell, guess, timestamp FROM cell_fills WHERE puzzle_id = ? ORDER BY timestamp, cell")?;
+        let fills = stmt.query_map(params![puzzle_id], |row| {
+            let cell: u32 = row.get(0)?;
+            Ok(CellFill { cell: cell as usize, guess: row.get(1)?, timestamp: row.get(2)? })
         })?;
-        let xwords: Result<Vec<XwordSummary>, rusqlite::Error> = rows.collect();
-        Ok(xwords?)
+        Ok(fills.collect::<Result<_, _>>()?)
+    }
+
+    /// Every answer of every stored puzzle, oldest first.
+    pub fn get_answers(&self) -> Result<Vec<AnswerUse>, DbError> {
+        let mut stmt = self.conn.prepare("SELECT p.date, p.puzzle_type, c.answer, c.text
+            FROM clues c JOIN puzzles p ON p.puzzle_id = c.puzzle_id
+            ORDER BY p.date, c.direction, CAST(c.label AS INTEGER)")?;
+        let mut rows = stmt.query(params![])?;
+        let mut answers = Vec::new();
+        while let Some(row) = rows.next()? {
+            let date: String = row.get(0)?;
+            let puzzle_type: String = row.get(1)?;
+            answers.push(AnswerUse {
+                print_date: parse_date(&date)?,
+                puzzle_type: puzzle_type.parse()?,
+                answer: row.get(2)?,
+                clue: row.get(3)?
+            });
+        }
+        Ok(answers)
+    }
+}
+
+fn schema_version(conn: &Connection) -> Result<usize, DbError> {
+    let version: i64 = conn.query_row("PRAGMA user_version", params![], |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle::Direction;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 3, day).unwrap()
+    }
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    // CAT / A#O / BED, with the corners circled and the middle row shaded.
+    fn puzzle() -> (Puzzle, PuzzleGrid) {
+        let puzzle = Puzzle {
+            puzzle_id: 7, print_date: date(1), puzzle_type: PuzzleType::Mini, title: None, constructors: vec!["A B".to_string()],
+            editor: None, width: 3, height: 3, has_rebus: false, notes: None
+        };
+        let cells = ["C", "A", "T", "A", "", "O", "B", "E", "D"].iter().enumerate().map(|(i, letter)| GridCell {
+            answer: Some(letter.to_string()).filter(|l| !l.is_empty()),
+            label: None,
+            circled: [0, 2, 6, 8].contains(&i),
+            shaded: (3..6).contains(&i) && !letter.is_empty()
+        }).collect();
+        let clue = |direction, label: &str, text: &str, cells: &[usize]| Clue {
+            direction, label: label.to_string(), text: text.to_string(), cells: cells.to_vec()
+        };
+        let clues = vec![
+            clue(Direction::Across, "1", "Feline", &[0, 1, 2]),
+            clue(Direction::Across, "3", "Place to sleep", &[6, 7, 8]),
+            clue(Direction::Down, "1", "Taxi", &[0, 3, 6]),
+            clue(Direction::Down, "2", "Large, hooting bird", &[2, 5, 8])
+        ];
+        (puzzle, PuzzleGrid { width: 3, height: 3, cells, clues })
+    }
+
+    #[test]
+    fn notes_are_listed_by_date_and_searched_literally() {
+        let db = Database::new(":memory:").unwrap();
+        let first = db.add_note(date(2), PuzzleType::Daily, "stuck on the 50% corner").unwrap();
+        db.add_note(date(1), PuzzleType::Mini, "Quick one").unwrap();
+        db.add_note(date(2), PuzzleType::Daily, "rebus in 17A").unwrap();
+
+        let texts = |notes: Vec<Note>| notes.into_iter().map(|n| n.text).collect::<Vec<_>>();
+        assert_eq!(texts(db.get_notes(None).unwrap()), ["Quick one", "stuck on the 50% corner", "rebus in 17A"]);
+        assert_eq!(texts(db.get_notes(Some(date(2))).unwrap()), ["stuck on the 50% corner", "rebus in 17A"]);
+        assert_eq!(texts(db.search_notes("QUICK").unwrap()), ["Quick one"]);
+        assert_eq!(texts(db.search_notes("50%").unwrap()), ["stuck on the 50% corner"]);
+        assert_eq!(texts(db.search_notes("%").unwrap()), ["stuck on the 50% corner"]);
+
+        assert!(db.delete_note(first).unwrap());
+        assert!(!db.delete_note(first).unwrap());
+        assert_eq!(texts(db.get_notes(Some(date(2))).unwrap()), ["rebus in 17A"]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn tags_are_added_once_and_removed_per_puzzle() {
+        let mut db = Database::new(":memory:").unwrap();
+        db.add_tags(date(1), PuzzleType::Daily, &tags(&["themeless", "fast"])).unwrap();
+        db.add_tags(date(1), PuzzleType::Daily, &tags(&["fast"])).unwrap();
+        db.add_tags(date(1), PuzzleType::Mini, &tags(&["fast"])).unwrap();
+        db.remove_tags(date(1), PuzzleType::Mini, &tags(&["fast", "unknown"])).unwrap();
+
+        let stored = db.get_tags().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[&(date(1), PuzzleType::Daily)], tags(&["fast", "themeless"]));
+    }
+
+    // A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("xword-tracker-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn restore_brings_back_a_backup() {
+        let dir = TempDir::new("restore");
+        let mut db = Database::new(":memory:").unwrap();
+        db.add_note(date(1), PuzzleType::Daily, "before").unwrap();
+        let first = db.backup(&dir.0, Some("test")).unwrap();
+        let second = db.backup(&dir.0, Some("test")).unwrap();
+        assert_ne!(first, second);
+        db.add_note(date(2), PuzzleType::Daily, "after").unwrap();
+
+        db.restore(&first).unwrap();
+        let notes = db.get_notes(None).unwrap();
+        assert_eq!(notes.iter().map(|n| n.text.as_str()).collect::<Vec<_>>(), ["before"]);
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn restore_rejects_a_newer_schema() {
+        let dir = TempDir::new("newer-schema");
+        let path = dir.0.join("newer.db");
+        Connection::open(&path).unwrap()
+            .execute_batch(&format!("PRAGMA user_version = {}", MIGRATIONS.len() + 1)).unwrap();
+
+        let mut db = Database::new(":memory:").unwrap();
+        db.add_note(date(1), PuzzleType::Daily, "kept").unwrap();
+        match db.restore(&path) {
+            Err(DbError::SchemaTooNew(version, known)) => assert_eq!((version, known), (MIGRATIONS.len() + 1, MIGRATIONS.len())),
+            other => panic!("expected SchemaTooNew, got {:?}", other)
+        }
+        assert_eq!(db.get_notes(None).unwrap().len(), 1);
+        assert!(matches!(Database::new(path.to_str().unwrap()), Err(DbError::SchemaTooNew(..))));
+    }
+
+    #[test]
+    fn stored_grid_keeps_circles_and_shading() {
+        let mut db = Database::new(":memory:").unwrap();
+        let (puzzle, grid) = puzzle();
+        db.save_puzzles(&[(puzzle.clone(), grid)]).unwrap();
+        assert!(db.get_puzzle_ids().unwrap().contains(&7));
+
+        let stored = db.get_puzzle_grid(&puzzle).unwrap();
+        let marks = |f: fn(&GridCell) -> bool| stored.cells.iter().enumerate().filter(|(_, c)| f(c)).map(|(i, _)| i).collect::<Vec<_>>();
+        assert_eq!(marks(|c| c.circled), [0, 2, 6, 8]);
+        assert_eq!(marks(|c| c.shaded), [3, 5]);
+        assert_eq!(stored.cells.iter().map(|c| c.answer.as_deref().unwrap_or("#")).collect::<String>(), "CATA#OBED");
+    }
+
+    #[test]
+    fn puzzles_saved_without_markup_are_fetched_again() {
+        let mut db = Database::new(":memory:").unwrap();
+        let (puzzle, grid) = puzzle();
+        db.save_puzzles(&[(puzzle, grid)]).unwrap();
+        db.conn.execute("UPDATE puzzles SET markup_saved = 0", params![]).unwrap();
+        assert!(db.get_puzzle_ids().unwrap().is_empty());
+    }
+}
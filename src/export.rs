@@ -0,0 +1,183 @@
+use crate::metric::Series;
+use crate::tracker::{PuzzleType, SolveSource, SolveState, XwordSummary};
+use crate::util::serialize_date;
+
+use chrono::prelude::*;
+use serde::Serialize;
+use thiserror::Error;
+
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Unknown export format: {0}")]
+    UnknownFormat(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Ndjson
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            _ => Err(ExportError::UnknownFormat(s.to_string()))
+        }
+    }
+}
+
+// One row of exported history. Field names are the column names of the
+// export schema, so don't rename them without bumping consumers.
+#[derive(Serialize, Debug)]
+pub struct ExportRow {
+    #[serde(serialize_with = "serialize_date")]
+    pub date: NaiveDate,
+    pub weekday: String,
+    pub puzzle_type: PuzzleType,
+    pub solved: bool,
+    pub gold: bool,
+    pub time_seconds: Option<u32>,
+    pub source: SolveSource
+}
+
+impl From<&XwordSummary> for ExportRow {
+    fn from(xword: &XwordSummary) -> Self {
+        let (solved, gold, time_seconds) = match xword.solve_state {
+            SolveState::Unsolved => (false, false, None),
+            SolveState::Solved => (true, false, None),
+            SolveState::Gold { time } => (true, true, Some(time))
+        };
+        ExportRow {
+            date: xword.print_date,
+            weekday: xword.print_date.weekday().to_string(),
+            puzzle_type: xword.puzzle_type,
+            solved,
+            gold,
+            time_seconds,
+            source: xword.source
+        }
+    }
+}
+
+pub fn export_xwords<W: Write>(xwords: &[XwordSummary], format: ExportFormat, writer: W) -> Result<(), ExportError> {
+    write_rows(xwords.iter().map(ExportRow::from), format, writer)
+}
+
+fn write_rows<T: Serialize, I: Iterator<Item = T>, W: Write>(rows: I, format: ExportFormat, mut writer: W) -> Result<(), ExportError> {
+    match format {
+        ExportFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            for row in rows {
+                csv_writer.serialize(row)?;
+            }
+            csv_writer.flush()?;
+        },
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &rows.collect::<Vec<_>>())?;
+            writeln!(writer)?;
+        },
+        ExportFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, &row)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// One point of an exported metric.
+#[derive(Serialize, Debug)]
+pub struct MetricRow<'a> {
+    pub group: &'a str,
+    #[serde(serialize_with = "serialize_date")]
+    pub date: NaiveDate,
+    pub value: f64
+}
+
+pub fn export_series<W: Write>(series: &Series, format: ExportFormat, writer: W) -> Result<(), ExportError> {
+    let rows = series.iter()
+        .flat_map(|(group, points)| points.iter().map(move |(date, value)| MetricRow { group: &group.label, date: *date, value: *value }));
+    write_rows(rows, format, writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{parse_records, read_records, ColumnMapping, ImportReport};
+
+    use structopt::StructOpt;
+
+    fn xword(day: u32, puzzle_type: PuzzleType, solve_state: SolveState) -> XwordSummary {
+        XwordSummary {
+            print_date: NaiveDate::from_ymd_opt(2021, 3, day).unwrap(),
+            puzzle_type,
+            solve_state,
+            source: SolveSource::Api
+        }
+    }
+
+    fn round_trip(format: ExportFormat) {
+        let xwords = vec![
+            xword(1, PuzzleType::Daily, SolveState::Unsolved),
+            xword(2, PuzzleType::Daily, SolveState::Solved),
+            xword(2, PuzzleType::Mini, SolveState::Gold { time: 47 }),
+            xword(3, PuzzleType::Daily, SolveState::Gold { time: 3725 })
+        ];
+        let mut buffer = Vec::new();
+        export_xwords(&xwords, format, &mut buffer).unwrap();
+
+        let mapping = ColumnMapping::from_iter(&["import"]);
+        let mut report = ImportReport::default();
+        let imported = parse_records(read_records(buffer.as_slice(), format).unwrap(), &mapping, &mut report);
+
+        assert!(report.invalid.is_empty(), "{:?}", report.invalid);
+        assert_eq!(imported.len(), xwords.len());
+        for (expected, actual) in xwords.iter().zip(&imported) {
+            assert_eq!(actual.print_date, expected.print_date);
+            assert_eq!(actual.puzzle_type, expected.puzzle_type);
+            assert_eq!(actual.solve_state, expected.solve_state);
+            assert_eq!(actual.source, expected.source);
+        }
+    }
+
+    #[test]
+    fn csv_export_imports_back() {
+        round_trip(ExportFormat::Csv);
+    }
+
+    #[test]
+    fn json_export_imports_back() {
+        round_trip(ExportFormat::Json);
+    }
+
+    #[test]
+    fn ndjson_export_imports_back() {
+        round_trip(ExportFormat::Ndjson);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::tracker::{OutlierMap, PuzzleType, SolveSource, SolveState, TagMap, XwordSummary};
+use crate::util::parse_date;
+
+use chrono::prelude::*;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Default)]
+pub struct XwordFilter {
+    /// Only include puzzles published on or after this date (YYYY-MM-DD)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    pub from: Option<NaiveDate>,
+
+    /// Only include puzzles published on or before this date (YYYY-MM-DD)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    pub to: Option<NaiveDate>,
+
+    /// Only include puzzles published on these weekdays (e.g. mon,sat)
+    #[structopt(long, use_delimiter = true)]
+    pub weekdays: Vec<Weekday>,
+
+    /// Only include puzzles of this type (daily, mini, midi, bonus)
+    #[structopt(long, default_value = "daily")]
+    pub puzzle_type: PuzzleType,
+
+    /// Skip puzzles that were never solved
+    #[structopt(long)]
+    pub solved_only: bool,
+
+    /// Skip solves that were entered by hand with `record`
+    #[structopt(long)]
+    pub exclude_manual: bool,
+
+    /// Skip solves flagged as outliers; see `outliers`
+    #[structopt(long)]
+    pub exclude_outliers: bool,
+
+    /// Only include puzzles carrying this tag; may be repeated
+    #[structopt(long = "tag", number_of_values = 1)]
+    pub tags: Vec<String>,
+
+    /// Skip puzzles carrying this tag; may be repeated
+    #[structopt(long = "exclude-tag", number_of_values = 1)]
+    pub exclude_tags: Vec<String>
+}
+
+impl XwordFilter {
+    pub fn uses_tags(&self) -> bool {
+        !self.tags.is_empty() || !self.exclude_tags.is_empty()
+    }
+
+    pub fn matches(&self, xword: &XwordSummary, tags: &[String], flagged: bool) -> bool {
+        if let Some(from) = self.from {
+            if xword.print_date < from {
+                return false;
+            }
+        }
+        if let Some(to) = self.to {
+            if xword.print_date > to {
+                return false;
+            }
+        }
+        if !self.weekdays.is_empty() && !self.weekdays.contains(&xword.print_date.weekday()) {
+            return false;
+        }
+        if xword.puzzle_type != self.puzzle_type {
+            return false;
+        }
+        if self.exclude_manual && xword.source == SolveSource::Manual {
+            return false;
+        }
+        if self.exclude_outliers && flagged {
+            return false;
+        }
+        if self.solved_only {
+            if let SolveState::Unsolved = xword.solve_state {
+                return false;
+            }
+        }
+        if !self.tags.is_empty() && !self.tags.iter().any(|t| tags.contains(t)) {
+            return false;
+        }
+        if self.exclude_tags.iter().any(|t| tags.contains(t)) {
+            return false;
+        }
+        true
+    }
+
+    pub fn apply(&self, xwords: Vec<XwordSummary>, tags: &TagMap, outliers: &OutlierMap) -> Vec<XwordSummary> {
+        xwords.into_iter().filter(|xword| {
+            let key = (xword.print_date, xword.puzzle_type);
+            let xword_tags = tags.get(&key).map(|t| t.as_slice()).unwrap_or(&[]);
+            self.matches(xword, xword_tags, outliers.get(&key).is_some_and(|o| o.flagged))
+        }).collect()
+    }
+}
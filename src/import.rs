@@ -0,0 +1,367 @@
+use crate::export::ExportFormat;
+use crate::tracker::{PuzzleType, SolveSource, SolveState, XwordSummary};
+use crate::util::parse_duration;
+
+use chrono::prelude::*;
+use serde_json::Value;
+use structopt::StructOpt;
+use thiserror::Error;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("Unknown conflict policy: {0}")]
+    UnknownPolicy(String),
+
+    #[error("Expected a JSON array or newline-delimited JSON objects")]
+    JsonShape,
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error)
+}
+
+/// What to do when an imported row's date is already in the database.
+#[derive(Debug, Clone, Copy)]
+pub enum ConflictPolicy {
+    /// Keep whatever is already stored, only filling in new dates.
+    Skip,
+    /// Merge the two rows, keeping whichever carries more information
+    /// (a gold time beats a plain solve beats unsolved). Gold times from
+    /// the API are kept over imported ones.
+    Upsert,
+    /// Replace the stored row with the imported one.
+    Overwrite
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = ImportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "upsert" => Ok(ConflictPolicy::Upsert),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            _ => Err(ImportError::UnknownPolicy(s.to_string()))
+        }
+    }
+}
+
+// Which source columns hold which fields. Defaults match the export schema.
+#[derive(StructOpt, Debug)]
+pub struct ColumnMapping {
+    /// Column holding the puzzle's publication date
+    #[structopt(long, default_value = "date")]
+    pub date_column: String,
+
+    /// Format of the date column, in strftime syntax
+    #[structopt(long, default_value = "%Y-%m-%d")]
+    pub date_format: String,
+
+    /// Column holding the puzzle type (daily, mini, ...); daily if missing
+    #[structopt(long, default_value = "puzzle_type")]
+    pub puzzle_type_column: String,
+
+    /// Column holding whether the puzzle was solved; inferred from the time if missing
+    #[structopt(long, default_value = "solved")]
+    pub solved_column: String,
+
+    /// Column holding whether the solve was gold; inferred from the time if missing
+    #[structopt(long, default_value = "gold")]
+    pub gold_column: String,
+
+    /// Column holding the solve time, as seconds, m:ss or h:mm:ss
+    #[structopt(long, default_value = "time_seconds")]
+    pub time_column: String,
+
+    /// Column holding where the row came from (api, manual, import); import if missing
+    #[structopt(long, default_value = "source")]
+    pub source_column: String
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub rows_read: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub skipped: usize,
+    pub invalid: Vec<(usize, String)>
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "rows read: {}", self.rows_read)?;
+        writeln!(f, "inserted:  {}", self.inserted)?;
+        writeln!(f, "updated:   {}", self.updated)?;
+        writeln!(f, "unchanged: {}", self.unchanged)?;
+        writeln!(f, "skipped:   {}", self.skipped)?;
+        writeln!(f, "invalid:   {}", self.invalid.len())?;
+        for (line, reason) in &self.invalid {
+            writeln!(f, "  record {}: {}", line, reason)?;
+        }
+        Ok(())
+    }
+}
+
+type RawRecord = HashMap<String, String>;
+
+pub fn read_records<R: Read>(reader: R, format: ExportFormat) -> Result<Vec<RawRecord>, ImportError> {
+    match format {
+        ExportFormat::Csv => {
+            let mut csv_reader = csv::Reader::from_reader(reader);
+            let headers = csv_reader.headers()?.clone();
+            let mut records = Vec::new();
+            for record in csv_reader.records() {
+                let record = record?;
+                records.push(headers.iter().map(|h| h.trim().to_string())
+                    .zip(record.iter().map(|v| v.trim().to_string()))
+                    .collect());
+            }
+            Ok(records)
+        },
+        ExportFormat::Json => {
+            match serde_json::from_reader(reader)? {
+                Value::Array(values) => values.into_iter().map(value_to_record).collect(),
+                _ => Err(ImportError::JsonShape)
+            }
+        },
+        ExportFormat::Ndjson => {
+            let mut records = Vec::new();
+            for line in BufReader::new(reader).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                records.push(value_to_record(serde_json::from_str(&line)?)?);
+            }
+            Ok(records)
+        }
+    }
+}
+
+fn value_to_record(value: Value) -> Result<RawRecord, ImportError> {
+    match value {
+        Value::Object(map) => Ok(map.into_iter().map(|(k, v)| {
+            let v = match v {
+                Value::Null => String::new(),
+                Value::String(s) => s,
+                other => other.to_string()
+            };
+            (k, v)
+        }).collect()),
+        _ => Err(ImportError::JsonShape)
+    }
+}
+
+/// Converts raw records into summaries, recording any rows that can't be
+/// understood (or repeat an earlier date) in the report instead of failing.
+pub fn parse_records(records: Vec<RawRecord>, mapping: &ColumnMapping, report: &mut ImportReport) -> Vec<XwordSummary> {
+    let mut seen: HashMap<(NaiveDate, PuzzleType), usize> = HashMap::new();
+    let mut xwords = Vec::new();
+    report.rows_read += records.len();
+
+    for (i, record) in records.iter().enumerate() {
+        let line = i + 1;
+        match parse_record(record, mapping) {
+            Ok(xword) => {
+                if let Some(first) = seen.insert((xword.print_date, xword.puzzle_type), line) {
+                    report.invalid.push((line, format!("duplicate date, already seen in record {}", first)));
+                } else {
+                    xwords.push(xword);
+                }
+            },
+            Err(reason) => report.invalid.push((line, reason))
+        }
+    }
+
+    xwords
+}
+
+fn parse_record(record: &RawRecord, mapping: &ColumnMapping) -> Result<XwordSummary, String> {
+    let field = |column: &str| record.get(column).map(|v| v.as_str()).filter(|v| !v.is_empty());
+
+    let date = field(&mapping.date_column).ok_or(format!("missing {}", mapping.date_column))?;
+    let date = NaiveDate::parse_from_str(date, &mapping.date_format)
+        .map_err(|e| format!("invalid date {:?}: {}", date, e))?;
+    let puzzle_type = field(&mapping.puzzle_type_column).map(str::parse::<PuzzleType>).transpose()
+        .map_err(|e| e.to_string())?.unwrap_or_default();
+    let time = field(&mapping.time_column).map(parse_duration).transpose()?;
+    let solved = field(&mapping.solved_column).map(parse_bool).transpose()?;
+    let gold = field(&mapping.gold_column).map(parse_bool).transpose()?;
+    let source = field(&mapping.source_column).map(str::parse::<SolveSource>).transpose()
+        .map_err(|e| e.to_string())?.unwrap_or(SolveSource::Import);
+
+    let solve_state = match (solved, gold, time) {
+        (Some(false), Some(true), _) => return Err("marked gold but not solved".to_string()),
+        (_, Some(true), None) => return Err("marked gold but has no time".to_string()),
+        (Some(false), _, _) => SolveState::Unsolved,
+        (_, Some(false), _) => SolveState::from_solved_and_time(solved.unwrap_or_else(|| time.is_some()), None),
+        (_, _, Some(time)) => SolveState::Gold { time },
+        (solved, _, None) => SolveState::from_solved_and_time(solved.unwrap_or(false), None)
+    };
+
+    Ok(XwordSummary {
+        print_date: date,
+        puzzle_type,
+        solve_state,
+        source
+    })
+}
+
+fn parse_bool(s: &str) -> Result<bool, String> {
+    match s.to_lowercase().as_str() {
+        "true" | "t" | "yes" | "y" | "1" => Ok(true),
+        "false" | "f" | "no" | "n" | "0" => Ok(false),
+        _ => Err(format!("invalid boolean {:?}", s))
+    }
+}
+
+/// Works out which rows to write given what's already stored, tallying the
+/// outcome of each incoming row in the report.
+pub fn plan_import(existing: &[XwordSummary], incoming: Vec<XwordSummary>, policy: ConflictPolicy, report: &mut ImportReport) -> Vec<XwordSummary> {
+    let existing: HashMap<_, _> = existing.iter().map(|x| ((x.print_date, x.puzzle_type), (x.solve_state, x.source))).collect();
+    let mut to_write = Vec::new();
+
+    for xword in incoming {
+        let (current, current_source) = match existing.get(&(xword.print_date, xword.puzzle_type)) {
+            Some(current) => *current,
+            None => {
+                report.inserted += 1;
+                to_write.push(xword);
+                continue;
+            }
+        };
+        let solve_state = match policy {
+            ConflictPolicy::Skip => {
+                report.skipped += 1;
+                continue;
+            },
+            ConflictPolicy::Upsert => merge(current, current_source, xword.solve_state),
+            ConflictPolicy::Overwrite => xword.solve_state
+        };
+        if solve_state == current {
+            report.unchanged += 1;
+        } else {
+            report.updated += 1;
+            to_write.push(XwordSummary { solve_state, ..xword });
+        }
+    }
+
+    to_write
+}
+
+fn merge(current: SolveState, current_source: SolveSource, incoming: SolveState) -> SolveState {
+    match (current, incoming) {
+        (SolveState::Gold { .. }, _) if current_source == SolveSource::Api => current,
+        (_, SolveState::Gold { .. }) => incoming,
+        (SolveState::Gold { .. }, _) => current,
+        (_, SolveState::Solved) => incoming,
+        _ => current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 3, day).unwrap()
+    }
+
+    fn xword(day: u32, solve_state: SolveState, source: SolveSource) -> XwordSummary {
+        XwordSummary { print_date: date(day), puzzle_type: PuzzleType::Daily, solve_state, source }
+    }
+
+    // Stored: the 1st solved, the 2nd gold in 5:00, the 3rd unsolved. Imported:
+    // gold on the 1st, solved on the 2nd, unsolved on the 3rd and a new 4th.
+    fn plan(policy: ConflictPolicy) -> (Vec<(NaiveDate, SolveState)>, ImportReport) {
+        let existing = vec![
+            xword(1, SolveState::Solved, SolveSource::Api),
+            xword(2, SolveState::Gold { time: 300 }, SolveSource::Api),
+            xword(3, SolveState::Unsolved, SolveSource::Api)
+        ];
+        let incoming = vec![
+            xword(1, SolveState::Gold { time: 200 }, SolveSource::Import),
+            xword(2, SolveState::Solved, SolveSource::Import),
+            xword(3, SolveState::Unsolved, SolveSource::Import),
+            xword(4, SolveState::Solved, SolveSource::Import)
+        ];
+        let mut report = ImportReport::default();
+        let planned = plan_import(&existing, incoming, policy, &mut report);
+        (planned.into_iter().map(|x| (x.print_date, x.solve_state)).collect(), report)
+    }
+
+    fn tally(report: &ImportReport) -> (usize, usize, usize, usize) {
+        (report.inserted, report.updated, report.unchanged, report.skipped)
+    }
+
+    #[test]
+    fn skip_only_inserts_new_dates() {
+        let (planned, report) = plan(ConflictPolicy::Skip);
+        assert_eq!(planned, vec![(date(4), SolveState::Solved)]);
+        assert_eq!(tally(&report), (1, 0, 0, 3));
+    }
+
+    #[test]
+    fn upsert_keeps_the_more_informative_row() {
+        let (planned, report) = plan(ConflictPolicy::Upsert);
+        assert_eq!(planned, vec![(date(1), SolveState::Gold { time: 200 }), (date(4), SolveState::Solved)]);
+        assert_eq!(tally(&report), (1, 1, 2, 0));
+    }
+
+    #[test]
+    fn overwrite_replaces_every_changed_row() {
+        let (planned, report) = plan(ConflictPolicy::Overwrite);
+        assert_eq!(planned, vec![
+            (date(1), SolveState::Gold { time: 200 }),
+            (date(2), SolveState::Solved),
+            (date(4), SolveState::Solved)
+        ]);
+        assert_eq!(tally(&report), (1, 2, 1, 0));
+    }
+
+    #[test]
+    fn merge_keeps_api_gold_times() {
+        let gold = SolveState::Gold { time: 300 };
+        assert_eq!(merge(gold, SolveSource::Api, SolveState::Gold { time: 200 }), gold);
+        assert_eq!(merge(gold, SolveSource::Import, SolveState::Gold { time: 200 }), SolveState::Gold { time: 200 });
+        assert_eq!(merge(SolveState::Unsolved, SolveSource::Api, SolveState::Solved), SolveState::Solved);
+        assert_eq!(merge(SolveState::Solved, SolveSource::Api, SolveState::Unsolved), SolveState::Solved);
+    }
+
+    #[test]
+    fn overwrite_replaces_an_api_gold_time() {
+        let existing = vec![xword(2, SolveState::Gold { time: 300 }, SolveSource::Api)];
+        let incoming = vec![xword(2, SolveState::Gold { time: 200 }, SolveSource::Import)];
+        let mut report = ImportReport::default();
+        let upserted = plan_import(&existing, incoming.clone(), ConflictPolicy::Upsert, &mut report);
+        assert!(upserted.is_empty());
+        let overwritten = plan_import(&existing, incoming, ConflictPolicy::Overwrite, &mut report);
+        assert_eq!(overwritten[0].solve_state, SolveState::Gold { time: 200 });
+        assert_eq!(overwritten[0].source, SolveSource::Import);
+    }
+
+    #[test]
+    fn source_column_is_kept_and_defaults_to_import() {
+        let record = |source: &str| -> RawRecord {
+            vec![("date", "2021-03-01"), ("time_seconds", "95"), ("source", source)].into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let mapping = ColumnMapping::from_iter(&["import"]);
+        assert_eq!(parse_record(&record("api"), &mapping).unwrap().source, SolveSource::Api);
+        assert_eq!(parse_record(&record("manual"), &mapping).unwrap().source, SolveSource::Manual);
+        assert_eq!(parse_record(&record(""), &mapping).unwrap().source, SolveSource::Import);
+        assert!(parse_record(&record("scanner"), &mapping).is_err());
+    }
+}
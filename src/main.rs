@@ -1,21 +1,602 @@
+mod chart;
 mod database;
+mod export;
+mod filter;
+mod import;
+mod metric;
 mod nytimes;
+mod puzfile;
+mod puzzle;
+mod replay;
+mod report;
 mod stats;
 mod tracker;
 mod util;
 
-use std::fs::read_to_string;
+use std::collections::HashMap;
+use std::fs::{read_to_string, File};
+use std::io::{stdout, Write};
+use std::path::PathBuf;
 
-use anyhow::Result;
-use tokio;
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use chrono_tz::America::New_York;
+use chrono_tz::Tz;
+use structopt::StructOpt;
 use yaml_rust::{Yaml, YamlLoader};
 
-use tracker::Tracker;
+use export::ExportFormat;
+use filter::XwordFilter;
+use import::{ColumnMapping, ConflictPolicy};
+use metric::{Metric, MetricKind, PlotOptions};
+use nytimes::NYTimesError;
+use puzfile::PuzFileFormat;
+use replay::ReplayFormat;
+use report::{write_report, Cell, ReportFormat};
+use stats::{
+    ewma, get_answer_frequencies, get_answer_frequencies_by_year, get_completion_reports, get_stall_summaries, length_group, pearson, AnswerFrequency,
+    CommonFillPoint, CompletionSummary, ConstructorSort, OutlierRules, Period, PeriodSummary, PersonalBest, Recency, StallSummary, Statistic, Streak,
+    Window
+};
+use tracker::{ClueField, PuzzleType, SolveState, Tracker, DEFAULT_ARCHIVE_START};
+use util::{date_to_string, format_duration, parse_date, parse_duration, parse_weekday_duration, string_to_date, WEEKDAYS};
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Track NYTimes crossword statistics")]
+struct Opt {
+    #[structopt(subcommand)]
+    command: Option<Command>
+}
+
+// Structs flattened into these commands (filters, plot options, column
+// mappings, outlier rules) must not have doc comments: structopt would show
+// a flattened struct's doc comment as the command's help instead of its own.
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Fetch new solves from the NYTimes and redraw the graphs (the default)
+    Sync {
+        /// Let the fetched results replace solves entered with `record`
+        #[structopt(long)]
+        overwrite_manual: bool,
+
+        /// Refetch solves from this date on, rather than from the last solve
+        #[structopt(long, parse(try_from_str = parse_date))]
+        since: Option<NaiveDate>
+    },
+
+    /// Redraw the graphs from the local database
+    Plot {
+        #[structopt(flatten)]
+        options: PlotOptions,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Tabulate, export or plot a single metric with the same options as `plot`
+    Metric {
+        /// One of times, gold-rate
+        metric: MetricKind,
+
+        /// Write every point as csv, json or ndjson rather than a table of latest values
+        #[structopt(short, long)]
+        format: Option<ExportFormat>,
+
+        /// File to write to; defaults to stdout, or to graphs/ with --plot
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Draw the chart instead
+        #[structopt(long, conflicts_with = "format")]
+        plot: bool,
+
+        #[structopt(flatten)]
+        options: PlotOptions,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Tables of solve time statistics for each weekday, optionally by year
+    Summary {
+        /// Statistics to show; see `plot --help`
+        #[structopt(long, use_delimiter = true, default_value = "mean,median,p25,p75,p90,trimmed10")]
+        stats: Vec<Statistic>,
+
+        /// Break each weekday down by year
+        #[structopt(long)]
+        by_year: bool,
+
+        /// Half-life of the exponentially weighted "form" column, in puzzles
+        /// (10) or days (30d)
+        #[structopt(long, default_value = "10")]
+        half_life: Window,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Progress through the daily archive by publication year, from the
+    /// archive_start in config.yaml (or 2015-06-01); `sync --since` fetches
+    /// older solves
+    Archive {
+        /// Break each year down by weekday
+        #[structopt(long)]
+        by_weekday: bool,
+
+        /// One of table, markdown, csv
+        #[structopt(short, long, default_value = "table")]
+        format: ReportFormat,
+
+        /// File to write to; defaults to stdout, or graphs/archive.png with --plot
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Draw a chart of the archive, a mark for each puzzle, instead
+        #[structopt(long)]
+        plot: bool
+    },
+
+    /// Monthly, quarterly or yearly aggregates for each weekday and overall
+    Report {
+        /// One of month, quarter, year
+        #[structopt(long, default_value = "year")]
+        period: Period,
+
+        /// One of table, markdown, csv
+        #[structopt(short, long, default_value = "table")]
+        format: ReportFormat,
+
+        /// File to write to; defaults to stdout
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Compare solves made the day a puzzle came out, within a week, and from
+    /// the archive; `sync --since` fills in timestamps for older solves
+    Recency {
+        /// Break each bucket down by weekday
+        #[structopt(long)]
+        by_weekday: bool,
+
+        /// One of table, markdown, csv
+        #[structopt(short, long, default_value = "table")]
+        format: ReportFormat,
+
+        /// File to write to; defaults to stdout
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// When solves were finished, by hour of the day or by weekday, in the
+    /// timezone from config.yaml (or America/New_York), and how times vary
+    /// with it
+    TimeOfDay {
+        /// Break solves down by the local weekday they were finished on instead
+        #[structopt(long)]
+        by_weekday: bool,
+
+        /// One of table, markdown, csv
+        #[structopt(short, long, default_value = "table")]
+        format: ReportFormat,
+
+        /// File to write to; defaults to stdout, or graphs/time_of_day.png with --plot
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Draw a heatmap of weekday against hour instead
+        #[structopt(long)]
+        plot: bool,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Fit a trend to each weekday's gold times and estimate the yearly
+    /// improvement, optionally projecting when a target time will be reached
+    Trends {
+        /// A target time for a weekday, e.g. sat=20:00; may be repeated
+        #[structopt(long = "target", parse(try_from_str = parse_weekday_duration))]
+        targets: Vec<(Weekday, u32)>,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Record a solve by hand, e.g. one done on paper or in another app
+    Record {
+        /// Publication date of the puzzle (YYYY-MM-DD)
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        /// One of daily, mini, midi, bonus
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType,
+
+        /// Solve time, as seconds, m:ss or h:mm:ss; without it the puzzle is marked solved but not gold
+        #[structopt(short, long, parse(try_from_str = parse_duration))]
+        time: Option<u32>,
+
+        /// Mark the puzzle as attempted but not solved
+        #[structopt(long, conflicts_with = "time")]
+        unsolved: bool,
+
+        /// Free-form notes about the solve
+        #[structopt(short, long)]
+        notes: Option<String>
+    },
+
+    /// Fetch title, constructor and grid details for puzzles synced before they were tracked
+    FetchPuzzles {
+        #[structopt(long, parse(try_from_str = parse_date))]
+        from: Option<NaiveDate>,
+
+        #[structopt(long, parse(try_from_str = parse_date))]
+        to: Option<NaiveDate>
+    },
+
+    /// Save solved puzzles, with our fill, as .ipuz or .puz files
+    ExportPuzzles {
+        /// One of ipuz, puz
+        #[structopt(short, long, default_value = "ipuz")]
+        format: PuzFileFormat,
+
+        /// Directory to write the puzzles to
+        #[structopt(short, long, parse(from_os_str), default_value = "archive")]
+        dir: PathBuf,
+
+        /// Rewrite puzzles that were already exported
+        #[structopt(long)]
+        overwrite: bool,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Search the clues and answers of solved puzzles, e.g. `search --answer esne`
+    Search {
+        #[structopt(required = true)]
+        query: Vec<String>,
+
+        /// Only match answers
+        #[structopt(short, long, conflicts_with = "clue")]
+        answer: bool,
+
+        /// Only match clue text
+        #[structopt(short, long)]
+        clue: bool,
+
+        /// Include puzzles we haven't solved, spoiling their answers
+        #[structopt(long)]
+        unsolved: bool,
+
+        #[structopt(short, long, default_value = "50")]
+        limit: u32
+    },
+
+    /// Show what's known about one puzzle
+    Show {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType
+    },
+
+    /// Replay the order in which a puzzle's cells were filled, to see where
+    /// the solve stalled
+    Replay {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType,
+
+        /// One of gif, frames
+        #[structopt(short, long, default_value = "gif")]
+        format: ReplayFormat,
+
+        /// File (or directory, for frames) to write to; defaults to replays/<date>-<type>
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>
+    },
+
+    /// Personal best gold times for each weekday and puzzle type
+    Bests {
+        /// Show every successive best rather than just the current ones
+        #[structopt(long)]
+        history: bool,
+
+        /// Only show this puzzle type (daily, mini, midi, bonus)
+        #[structopt(short = "p", long)]
+        puzzle_type: Option<PuzzleType>
+    },
+
+    /// Current and longest streaks of daily puzzles solved before the next
+    /// one was released, as the NYTimes counts them
+    Streaks {
+        /// Count solves whose solve time is unknown (see `sync --since`)
+        #[structopt(long)]
+        lenient: bool,
+
+        /// Also draw the streak history to graphs/streaks.png
+        #[structopt(long)]
+        plot: bool
+    },
+
+    /// Find the entries and parts of the grid where solves stall
+    Stalls(StallCommand),
+
+    /// Flag gold times that aren't real solving, so stats can skip them with
+    /// --exclude-outliers
+    Outliers(OutlierCommand),
+
+    /// Compare solve times and gold rates by constructor
+    Constructors {
+        /// Only show constructors with at least this many puzzles
+        #[structopt(long, default_value = "3")]
+        min_count: u32,
+
+        /// Sort by median relative time (slowest first), count, or name
+        #[structopt(long, default_value = "relative")]
+        sort: ConstructorSort,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Study the most common answers and how common fill affects our times
+    Answers(AnswerCommand),
+
+    /// Add, list and search notes on puzzles
+    Note(NoteCommand),
+
+    /// Tag puzzles and summarise solves by tag
+    Tag(TagCommand),
+
+    /// Write a timestamped copy of the database
+    Backup {
+        /// Directory to write the backup to
+        #[structopt(short, long, parse(from_os_str))]
+        dir: Option<PathBuf>
+    },
+
+    /// Replace the database with a backup
+    Restore {
+        #[structopt(parse(from_os_str))]
+        backup: PathBuf
+    },
+
+    /// Rebuild the database file to reclaim unused space
+    Vacuum,
+
+    /// Check the database file for corruption
+    IntegrityCheck,
+
+    /// Export solve history as csv, json or ndjson
+    Export {
+        /// One of csv, json, ndjson
+        #[structopt(short, long, default_value = "csv")]
+        format: ExportFormat,
+
+        /// File to write to; defaults to stdout
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    },
+
+    /// Import solve history from a csv, json or ndjson file
+    Import {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// One of csv, json, ndjson; guessed from the file extension if omitted
+        #[structopt(short, long)]
+        format: Option<ExportFormat>,
+
+        /// What to do with dates already in the database: upsert, skip or overwrite
+        #[structopt(long, default_value = "upsert")]
+        on_conflict: ConflictPolicy,
+
+        /// Only validate the file and report what would change
+        #[structopt(long)]
+        dry_run: bool,
+
+        #[structopt(flatten)]
+        mapping: ColumnMapping
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum AnswerCommand {
+    /// The most frequent answers overall, or for each year
+    Top {
+        #[structopt(short, long, default_value = "25")]
+        limit: usize,
+
+        /// Ignore answers shorter than this
+        #[structopt(long, default_value = "3")]
+        min_length: usize,
+
+        /// Show a separate list for each publication year
+        #[structopt(long)]
+        by_year: bool
+    },
+
+    /// Answers seen at least a given number of times, with their latest clue
+    Repeaters {
+        #[structopt(long, default_value = "5")]
+        min_count: u32,
+
+        #[structopt(long, default_value = "3")]
+        min_length: usize
+    },
+
+    /// How our times relate to the share of common answers in a grid
+    CommonFill {
+        /// How many other puzzles an answer must appear in to count as common
+        #[structopt(long, default_value = "5")]
+        min_count: u32,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum StallCommand {
+    /// The entries of one puzzle that held us up longest
+    Report {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType,
+
+        #[structopt(short, long, default_value = "10")]
+        limit: usize
+    },
+
+    /// Kinds of entry (direction, length, region) that hold us up, across puzzles
+    Patterns {
+        /// Only show combinations seen at least this many times
+        #[structopt(long, default_value = "10")]
+        min_count: u32,
+
+        #[structopt(short, long, default_value = "10")]
+        limit: usize,
+
+        #[structopt(flatten)]
+        filter: XwordFilter
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum OutlierCommand {
+    /// Flag outliers by the given rules, replacing earlier detection; with no
+    /// rules, flags times more than 3 IQRs above the upper quartile. Rerun
+    /// after syncing to check new solves
+    Detect {
+        #[structopt(flatten)]
+        rules: OutlierRules,
+
+        /// Only show what would be flagged
+        #[structopt(long)]
+        dry_run: bool
+    },
+
+    /// List flagged solves and manual overrides
+    List,
+
+    /// Flag a solve by hand, whatever detection says
+    Flag {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType
+    },
+
+    /// Never flag a solve, whatever detection says
+    Keep {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType
+    },
+
+    /// Drop a flag or override, leaving the solve to the next detection
+    Clear {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum NoteCommand {
+    /// Attach a note to a puzzle
+    Add {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        text: String,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType
+    },
+
+    /// List notes, optionally only those for one date
+    List {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: Option<NaiveDate>
+    },
+
+    /// Find notes containing some text
+    Search {
+        query: String
+    },
+
+    /// Delete a note by id
+    Delete {
+        id: i64
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum TagCommand {
+    /// Tag a puzzle, e.g. `tag add 2020-01-04 rebus theme-hard`
+    Add {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        #[structopt(required = true)]
+        tags: Vec<String>,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType
+    },
+
+    /// Remove tags from a puzzle
+    Remove {
+        #[structopt(parse(try_from_str = parse_date))]
+        date: NaiveDate,
+
+        #[structopt(required = true)]
+        tags: Vec<String>,
+
+        #[structopt(short = "p", long, default_value = "daily")]
+        puzzle_type: PuzzleType
+    },
+
+    /// List tagged puzzles, optionally only those carrying a tag
+    List {
+        tag: Option<String>
+    },
+
+    /// Show solve counts, gold rates and mean times for each tag
+    Stats {
+        #[structopt(flatten)]
+        filter: XwordFilter
+    }
+}
 
 //#[tokio::main(core_threads=4, max_threads=8)]
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
+    let opt = Opt::from_args();
     let config = config()?;
 
     // let path = PathBuf::from(r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe");
@@ -25,14 +606,633 @@ async fn main() -> Result<()> {
     // login(&tab, &config)?;
 
     let session = config["session"].as_str().expect("Failed to get session id").to_string();
-    let mut tracker = Tracker::new(session)?;
-    tracker.update_times().await?;
-    tracker.plot_stats()?;
-    // tracker.foo().await?;
-    //tracker.get_xwords().await?;
-    //tracker.update_times().await?;
-    //tracker.update_times()?;
+    let archive_start = match config["archive_start"].as_str() {
+        Some(start) => parse_date(start)?,
+        None => string_to_date(DEFAULT_ARCHIVE_START)
+    };
+    let timezone: Tz = match config["timezone"].as_str() {
+        Some(name) => name.parse().map_err(|e: String| anyhow!(e))?,
+        None => New_York
+    };
+    let mut tracker = Tracker::new(session, archive_start, timezone)?;
+    match opt.command.unwrap_or(Command::Sync { overwrite_manual: false, since: None }) {
+        Command::Sync { overwrite_manual, since } => {
+            let failed = tracker.update_times(overwrite_manual, since).await?;
+            if !failed.is_empty() {
+                print_failed_puzzles(&failed);
+                println!("couldn't get details for {} puzzles; run fetch-puzzles to retry", failed.len());
+            }
+            print_new_bests(&tracker.update_personal_bests()?);
+            tracker.plot_stats(&XwordFilter::default(), &PlotOptions::default())?;
+        },
+        Command::Plot { options, filter } => tracker.plot_stats(&filter, &options)?,
+        Command::Metric { metric, format, output, plot, options, filter } => {
+            let metric = options.metric(metric);
+            metric_report(&tracker, metric.as_ref(), format, output, plot, &filter)?
+        },
+        Command::Summary { stats, by_year, half_life, filter } => summary(&tracker, &stats, by_year, half_life, &filter)?,
+        Command::Archive { by_weekday, format, output, plot } => {
+            if plot {
+                let path = output.unwrap_or_else(|| PathBuf::from("graphs/archive.png"));
+                tracker.plot_archive(&path)?;
+                println!("wrote {}", path.display());
+            } else {
+                match output {
+                    Some(path) => archive(&tracker, by_weekday, format, File::create(path)?)?,
+                    None => archive(&tracker, by_weekday, format, stdout())?
+                }
+            }
+        },
+        Command::Report { period, format, output, filter } => match output {
+            Some(path) => report(&tracker, period, format, &filter, File::create(path)?)?,
+            None => report(&tracker, period, format, &filter, stdout())?
+        },
+        Command::Recency { by_weekday, format, output, filter } => match output {
+            Some(path) => recency(&tracker, by_weekday, format, &filter, File::create(path)?)?,
+            None => recency(&tracker, by_weekday, format, &filter, stdout())?
+        },
+        Command::TimeOfDay { by_weekday, format, output, plot, filter } => {
+            if plot {
+                let path = output.unwrap_or_else(|| PathBuf::from("graphs/time_of_day.png"));
+                tracker.plot_solve_clock(&filter, &path)?;
+                println!("wrote {}", path.display());
+            } else {
+                match output {
+                    Some(path) => time_of_day(&tracker, by_weekday, format, &filter, File::create(path)?)?,
+                    None => time_of_day(&tracker, by_weekday, format, &filter, stdout())?
+                }
+            }
+        },
+        Command::Trends { targets, filter } => trends(&tracker, &targets, &filter)?,
+        Command::Record { date, puzzle_type, time, unsolved, notes } => {
+            let solve_state = SolveState::from_solved_and_time(!unsolved, time);
+            tracker.record(date, puzzle_type, solve_state, notes.as_deref())?;
+            print_new_bests(&tracker.update_personal_bests()?);
+        },
+        Command::FetchPuzzles { from, to } => {
+            let (fetched, failed) = tracker.backfill_puzzles(from, to).await?;
+            print_failed_puzzles(&failed);
+            println!("fetched {} puzzles", fetched);
+            if !failed.is_empty() {
+                println!("{} failed; run fetch-puzzles again to retry", failed.len());
+            }
+        },
+        Command::ExportPuzzles { format, dir, overwrite, filter } => {
+            let (written, missing) = tracker.export_puzzles(&filter, format, &dir, overwrite)?;
+            println!("wrote {} puzzles to {}", written.len(), dir.display());
+            if missing > 0 {
+                println!("skipped {} solves whose puzzles aren't stored; run fetch-puzzles to get them", missing);
+            }
+        },
+        Command::Search { query, answer, clue, unsolved, limit } => {
+            let field = match (answer, clue) {
+                (true, _) => Some(ClueField::Answer),
+                (_, true) => Some(ClueField::Clue),
+                _ => None
+            };
+            for m in tracker.search_clues(&query.join(" "), field, unsolved, limit)? {
+                let time = match m.solve_state {
+                    SolveState::Gold { time } => format_duration(time as f64),
+                    SolveState::Solved => "solved".to_string(),
+                    SolveState::Unsolved => "unsolved".to_string()
+                };
+                println!("{} {:<3} {:>8}  {:>3}{} {:<15} {}", date_to_string(&m.print_date), m.print_date.weekday(), time,
+                    m.label, &m.direction.as_str()[..1], m.answer, m.text);
+            }
+        },
+        Command::Show { date, puzzle_type } => show(&tracker, date, puzzle_type)?,
+        Command::Replay { date, puzzle_type, format, output } => {
+            let output = output.unwrap_or_else(|| {
+                let name = format!("{}-{}", date_to_string(&date), puzzle_type);
+                match format {
+                    ReplayFormat::Gif => PathBuf::from("replays").join(name + ".gif"),
+                    ReplayFormat::Frames => PathBuf::from("replays").join(name)
+                }
+            });
+            match tracker.replay(date, puzzle_type, format, &output)? {
+                None => println!("no puzzle details for {} {}; try fetch-puzzles", date_to_string(&date), puzzle_type),
+                Some(written) if written.is_empty() => println!("no fill times recorded for {} {}", date_to_string(&date), puzzle_type),
+                Some(written) => println!("wrote {} to {}", if written.len() == 1 { "replay".to_string() } else { format!("{} frames", written.len()) }, output.display())
+            }
+        },
+        Command::Bests { history, puzzle_type } => bests(&tracker, history, puzzle_type)?,
+        Command::Streaks { lenient, plot } => {
+            let (all, gold) = tracker.streaks(lenient)?;
+            let streak = |s: Option<Streak>| s
+                .map(|s| format!("{:>5}  {} to {}", s.length, date_to_string(&s.start), date_to_string(&s.end)))
+                .unwrap_or_else(|| format!("{:>5}", 0));
+            for (name, summary) in &[("all solves", &all), ("gold", &gold)] {
+                println!("{}", name);
+                println!("  current {}", streak(summary.current));
+                println!("  longest {}", streak(summary.longest));
+            }
+            if all.unknown > 0 && !lenient {
+                println!("{} solves without a known solve time were not counted; try sync --since or --lenient", all.unknown);
+            }
+            if plot {
+                tracker.plot_streaks(&all, &gold);
+            }
+        },
+        Command::Stalls(command) => stalls(&tracker, command)?,
+        Command::Outliers(command) => outliers(&mut tracker, command)?,
+        Command::Constructors { min_count, sort, filter } => constructors(&tracker, min_count, sort, &filter)?,
+        Command::Answers(command) => answers(&tracker, command)?,
+        Command::Note(command) => note(&tracker, command)?,
+        Command::Tag(command) => tag(&mut tracker, command)?,
+        Command::Backup { dir } => {
+            let path = tracker.backup(dir.as_deref())?;
+            println!("backed up to {}", path.display());
+        },
+        Command::Restore { backup } => {
+            let previous = tracker.restore(&backup)?;
+            println!("restored {}; the previous database was saved to {}", backup.display(), previous.display());
+        },
+        Command::Vacuum => tracker.vacuum()?,
+        Command::IntegrityCheck => {
+            let problems = tracker.integrity_check()?;
+            if problems.is_empty() {
+                println!("ok");
+            }
+            for problem in problems {
+                println!("{}", problem);
+            }
+        },
+        Command::Export { format, output, filter } => match output {
+            Some(path) => tracker.export(&filter, format, File::create(path)?)?,
+            None => tracker.export(&filter, format, stdout())?
+        },
+        Command::Import { input, format, on_conflict, dry_run, mapping } => {
+            let format = format.or_else(|| ExportFormat::from_path(&input)).unwrap_or(ExportFormat::Csv);
+            let report = tracker.import(File::open(&input)?, format, &mapping, on_conflict, dry_run)?;
+            print!("{}", report);
+            if !dry_run {
+                print_new_bests(&tracker.update_personal_bests()?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn show(tracker: &Tracker, date: NaiveDate, puzzle_type: PuzzleType) -> Result<()> {
+    println!("{} {} {}", date_to_string(&date), date.weekday(), puzzle_type);
+    match tracker.puzzle(date, puzzle_type)? {
+        Some(puzzle) => {
+            if let Some(title) = &puzzle.title {
+                println!("title:        {}", title);
+            }
+            println!("constructors: {}", puzzle.constructors.join(", "));
+            if let Some(editor) = &puzzle.editor {
+                println!("editor:       {}", editor);
+            }
+            println!("size:         {}{}", puzzle.size(), if puzzle.has_rebus { ", rebus" } else { "" });
+            if let Some(notes) = &puzzle.notes {
+                println!("notes:        {}", notes);
+            }
+        },
+        None => println!("no puzzle details; try fetch-puzzles")
+    }
+    Ok(())
+}
+
+fn constructors(tracker: &Tracker, min_count: u32, sort: ConstructorSort, filter: &XwordFilter) -> Result<()> {
+    let mut summaries = tracker.constructor_summaries(filter)?.into_iter()
+        .filter(|(_, summary)| summary.count >= min_count)
+        .collect::<Vec<_>>();
+    match sort {
+        ConstructorSort::Count => summaries.sort_by_key(|(_, summary)| std::cmp::Reverse(summary.count)),
+        ConstructorSort::Relative => summaries.sort_by(|a, b| {
+            let (a, b) = (a.1.median_relative().unwrap_or(0.0), b.1.median_relative().unwrap_or(0.0));
+            b.total_cmp(&a)
+        }),
+        ConstructorSort::Name => ()
+    }
+
+    println!("{:<30} {:>5} {:>6} {:>8} {:>8}  best", "constructor", "count", "gold", "mean", "median");
+    let ratio = |r: Option<f64>| r.map(|r| format!("{:+.0}%", (r - 1.0) * 100.0)).unwrap_or_default();
+    for (name, summary) in summaries {
+        let best = summary.best_time
+            .map(|(date, time)| format!("{} ({} {})", format_duration(time as f64), date.weekday(), date_to_string(&date)))
+            .unwrap_or_default();
+        println!("{:<30} {:>5} {:>5.0}% {:>8} {:>8}  {}", name, summary.count, summary.gold_rate() * 100.0,
+            ratio(summary.mean_relative()), ratio(summary.median_relative()), best);
+    }
+    Ok(())
+}
+
+fn summary(tracker: &Tracker, stats: &[Statistic], by_year: bool, half_life: Window, filter: &XwordFilter) -> Result<()> {
+    let times = tracker.gold_times(filter, by_year)?;
+
+    print!("{:<9} {:>5}", "", "count");
+    for stat in stats {
+        print!(" {:>10}", stat);
+    }
+    println!(" {:>10}", "form");
+    for ((year, weekday), times) in times {
+        let day = WEEKDAYS[weekday as usize];
+        let name = match year {
+            Some(year) => format!("{} {}", year, day),
+            None => day.to_string()
+        };
+        print!("{:<9} {:>5}", name, times.len());
+        let values = times.iter().map(|(_, time)| *time).collect::<Vec<_>>();
+        for stat in stats {
+            print!(" {:>10}", stat.apply(&values).map(format_duration).unwrap_or_default());
+        }
+        let form = ewma(&times, half_life, 1).last().map(|(_, time)| format_duration(*time)).unwrap_or_default();
+        println!(" {:>10}", form);
+    }
+    Ok(())
+}
+
+fn metric_report(tracker: &Tracker, metric: &dyn Metric, format: Option<ExportFormat>, output: Option<PathBuf>, plot: bool,
+    filter: &XwordFilter) -> Result<()> {
+    if plot {
+        let path = output.unwrap_or_else(|| PathBuf::from("graphs").join(format!("{}.png", metric.file_stem())));
+        tracker.plot_metric(metric, filter, &path)?;
+        println!("wrote {}", path.display());
+        return Ok(());
+    }
+    if let Some(format) = format {
+        match output {
+            Some(path) => tracker.export_metric(metric, filter, format, File::create(path)?)?,
+            None => tracker.export_metric(metric, filter, format, stdout())?
+        }
+        return Ok(());
+    }
+
+    let unit = metric.unit();
+    println!("{}", metric.title());
+    println!("{:<9} {:>6} {:>10} {:>10} {:>10} {:>10}", "", "points", "since", "latest", "min", "max");
+    for (group, points) in tracker.metric_series(metric, filter)? {
+        let values = points.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        println!("{:<9} {:>6} {:>10} {:>10} {:>10} {:>10}", group.label, points.len(), date_to_string(&points[0].0),
+            unit.format(values[values.len() - 1]), unit.format(min), unit.format(max));
+    }
+    Ok(())
+}
+
+fn archive<W: Write>(tracker: &Tracker, by_weekday: bool, format: ReportFormat, writer: W) -> Result<()> {
+    let row = |year: i32, day: &str, summary: &CompletionSummary| vec![
+        Cell::Text(year.to_string()),
+        Cell::Text(day.to_string()),
+        Cell::Count(summary.puzzles),
+        Cell::Count(summary.solved),
+        Cell::Count(summary.gold),
+        Cell::Count(summary.unsolved),
+        Cell::Count(summary.untouched()),
+        Cell::Rate(summary.solved_share())
+    ];
+    let mut rows = Vec::new();
+    for (year, report) in get_completion_reports(&tracker.archive()?) {
+        if by_weekday {
+            for (day, summary) in &report.by_weekday {
+                rows.push(row(year, &WEEKDAYS[*day as usize].to_string(), summary));
+            }
+        }
+        rows.push(row(year, "All", &report.overall));
+    }
+    let headers = ["year", "weekday", "puzzles", "solved", "gold", "unsolved", "not started", "done"];
+    write_report(&headers, &rows, format, writer)?;
+    Ok(())
+}
+
+fn report<W: Write>(tracker: &Tracker, period: Period, format: ReportFormat, filter: &XwordFilter, writer: W) -> Result<()> {
+    let row = |label: &str, day: &str, summary: &PeriodSummary| vec![
+        Cell::Text(label.to_string()),
+        Cell::Text(day.to_string()),
+        Cell::Count(summary.count),
+        Cell::Count(summary.solved),
+        Cell::Count(summary.gold()),
+        Cell::Rate(summary.gold_rate()),
+        Cell::from(summary.mean()),
+        Cell::from(summary.median()),
+        Cell::from(summary.best())
+    ];
+    let mut rows = Vec::new();
+    for (label, report) in tracker.period_reports(filter, period)? {
+        for (day, summary) in &report.by_weekday {
+            rows.push(row(&label, &WEEKDAYS[*day as usize].to_string(), summary));
+        }
+        rows.push(row(&label, "All", &report.overall));
+    }
+    let headers = ["period", "weekday", "puzzles", "solved", "gold", "gold rate", "mean", "median", "best"];
+    write_report(&headers, &rows, format, writer)?;
+    Ok(())
+}
+
+fn recency<W: Write>(tracker: &Tracker, by_weekday: bool, format: ReportFormat, filter: &XwordFilter, writer: W) -> Result<()> {
+    let row = |recency: Recency, day: &str, summary: &PeriodSummary| vec![
+        Cell::Text(recency.label().to_string()),
+        Cell::Text(day.to_string()),
+        Cell::Count(summary.count),
+        Cell::Count(summary.gold()),
+        Cell::Rate(summary.gold_rate()),
+        Cell::from(summary.mean()),
+        Cell::from(summary.median()),
+        Cell::from(summary.best())
+    ];
+    let mut rows = Vec::new();
+    for (recency, report) in tracker.recency_reports(filter)? {
+        if by_weekday {
+            for (day, summary) in &report.by_weekday {
+                rows.push(row(recency, &WEEKDAYS[*day as usize].to_string(), summary));
+            }
+        }
+        rows.push(row(recency, "All", &report.overall));
+    }
+    // Only solves carry timestamps, so the share gold is of solves, not of
+    // puzzles as in `report`.
+    let headers = ["solved", "weekday", "solves", "gold", "gold/solved", "mean", "median", "best"];
+    write_report(&headers, &rows, format, writer)?;
+    Ok(())
+}
+
+fn time_of_day<W: Write>(tracker: &Tracker, by_weekday: bool, format: ReportFormat, filter: &XwordFilter, writer: W) -> Result<()> {
+    let clock = tracker.solve_clock(filter)?;
+    let (label, buckets) = if by_weekday {
+        ("weekday", clock.by_weekday.iter().map(|(day, summary)| (WEEKDAYS[*day as usize].to_string(), summary)).collect::<Vec<_>>())
+    } else {
+        ("hour", clock.by_hour.iter().map(|(hour, summary)| (format!("{:02}:00", hour), summary)).collect())
+    };
+    let rows = buckets.into_iter().map(|(bucket, summary)| vec![
+        Cell::Text(bucket),
+        Cell::Count(summary.solves.count),
+        Cell::Count(summary.solves.gold()),
+        Cell::Rate(summary.solves.gold_rate()),
+        Cell::from(summary.solves.mean()),
+        Cell::from(summary.solves.median()),
+        summary.median_relative().map(Cell::Relative).unwrap_or(Cell::Empty)
+    ]).collect::<Vec<_>>();
+    let headers = [label, "solves", "gold", "gold/solved", "mean", "median", "vs usual"];
+    write_report(&headers, &rows, format, writer)?;
+    Ok(())
+}
+
+fn trends(tracker: &Tracker, targets: &[(Weekday, u32)], filter: &XwordFilter) -> Result<()> {
+    let percent = |change: f64| format!("{:+.1}%", change * 100.0);
+    println!("{:<4} {:>5} {:>8} {:>9} {:>18}  target", "", "count", "trend", "per year", "95% interval");
+    for (day, trend) in tracker.trends(filter)? {
+        let day = WEEKDAYS[day as usize];
+        let (low, high) = trend.yearly_change_interval();
+        print!("{:<4} {:>5} {:>8} {:>9} {:>18}", day, trend.count, format_duration(trend.latest()),
+            percent(trend.yearly_change()), format!("{} to {}", percent(low), percent(high)));
+        for (_, target) in targets.iter().filter(|(d, _)| *d == day) {
+            let crossing = trend.crossing(*target as f64);
+            let when = if trend.latest() <= *target as f64 {
+                match crossing {
+                    Some(date) if date <= trend.end => format!("reached {}", date_to_string(&date)),
+                    _ => "reached".to_string()
+                }
+            } else {
+                match crossing {
+                    Some(date) if date > trend.end => format!("projected {}", date_to_string(&date)),
+                    _ => "not on this trend".to_string()
+                }
+            };
+            print!("  {} {}", format_duration(*target as f64), when);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn format_best(best: &PersonalBest) -> String {
+    let previous = best.previous
+        .map(|previous| format!("  (was {}, -{})", format_duration(previous as f64), format_duration((previous - best.time) as f64)))
+        .unwrap_or_default();
+    format!("{:<6} {} {}  {:>8}{}", best.puzzle_type.as_str(), best.print_date.weekday(), date_to_string(&best.print_date),
+        format_duration(best.time as f64), previous)
+}
+
+fn print_failed_puzzles(failed: &[(u32, NYTimesError)]) {
+    for (id, e) in failed {
+        println!("failed to get puzzle {}: {}", id, e);
+    }
+}
+
+fn print_new_bests(bests: &[PersonalBest]) {
+    for best in bests {
+        println!("new personal best! {}", format_best(best));
+    }
+}
+
+fn bests(tracker: &Tracker, history: bool, puzzle_type: Option<PuzzleType>) -> Result<()> {
+    let mut bests = tracker.personal_bests()?.into_iter()
+        .filter(|b| puzzle_type.is_none_or(|t| b.puzzle_type == t))
+        .collect::<Vec<_>>();
+    if !history {
+        let mut current: HashMap<_, PersonalBest> = HashMap::new();
+        for best in bests {
+            current.insert((best.puzzle_type, best.print_date.weekday().num_days_from_monday()), best);
+        }
+        bests = current.into_values().collect();
+        bests.sort_by_key(|b| (b.puzzle_type, b.print_date.weekday().num_days_from_monday()));
+    }
+    for best in &bests {
+        println!("{}", format_best(best));
+    }
+    Ok(())
+}
+
+fn stalls(tracker: &Tracker, command: StallCommand) -> Result<()> {
+    match command {
+        StallCommand::Report { date, puzzle_type, limit } => {
+            let mut entries = match tracker.stall_report(date, puzzle_type)? {
+                Some(entries) if entries.is_empty() => {
+                    println!("no fill times recorded for {} {}", date_to_string(&date), puzzle_type);
+                    return Ok(());
+                },
+                Some(entries) => entries,
+                None => {
+                    println!("no puzzle details for {} {}; try fetch-puzzles", date_to_string(&date), puzzle_type);
+                    return Ok(());
+                }
+            };
+            entries.sort_by(|a, b| b.stall.total_cmp(&a.stall));
+            println!("{:<5} {:<15} {:>6} {:>7} {:>7} {:>9}  clue", "entry", "answer", "region", "stall", "span", "completed");
+            for entry in entries.iter().take(limit) {
+                println!("{:<5} {:<15} {:>6} {:>7} {:>7} {:>9}  {}", format!("{}{}", entry.label, &entry.direction.as_str()[..1]),
+                    entry.answer, entry.region, format_duration(entry.stall), format_duration(entry.span as f64),
+                    format_duration(entry.completed as f64), entry.text);
+            }
+        },
+        StallCommand::Patterns { min_count, limit, filter } => {
+            let entries = tracker.entry_times(&filter)?;
+            if entries.is_empty() {
+                println!("no fill times recorded for matching puzzles");
+                return Ok(());
+            }
+            println!("time per cell relative to each puzzle's average, over {} entries", entries.len());
+            let print = |name: &str, summary: &StallSummary| {
+                println!("{:<32} {:>6} {:>+8.0}%", name, summary.count, (summary.mean_relative() - 1.0) * 100.0);
+            };
+            for (direction, summary) in get_stall_summaries(&entries, |e| e.direction) {
+                print(direction.as_str(), &summary);
+            }
+            for length in &["short", "medium", "long"] {
+                if let Some(summary) = get_stall_summaries(&entries, |e| length_group(e.length)).get(length) {
+                    print(&format!("{} entries", length), summary);
+                }
+            }
+            for (region, summary) in get_stall_summaries(&entries, |e| e.region) {
+                print(region, &summary);
+            }
+
+            let mut combinations = get_stall_summaries(&entries, |e| (length_group(e.length), e.direction, e.region)).into_iter()
+                .filter(|(_, summary)| summary.count >= min_count)
+                .collect::<Vec<_>>();
+            combinations.sort_by(|a, b| b.1.mean_relative().total_cmp(&a.1.mean_relative()));
+            println!();
+            println!("slowest combinations seen at least {} times", min_count);
+            for ((length, direction, region), summary) in combinations.iter().take(limit) {
+                print(&format!("{} {} entries in {}", length, direction.as_str().to_lowercase(), region), summary);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn answers(tracker: &Tracker, command: AnswerCommand) -> Result<()> {
+    let print_frequencies = |frequencies: Vec<AnswerFrequency>, min_length: usize, limit: usize| {
+        for frequency in frequencies.into_iter().filter(|f| f.answer.chars().count() >= min_length).take(limit) {
+            println!("{:<15} {:>5}  {} to {}  {}", frequency.answer, frequency.count,
+                date_to_string(&frequency.first_seen), date_to_string(&frequency.last_seen), frequency.last_clue);
+        }
+    };
+    match command {
+        AnswerCommand::Top { limit, min_length, by_year } => {
+            let answers = tracker.answers()?;
+            if by_year {
+                for (year, frequencies) in get_answer_frequencies_by_year(&answers) {
+                    println!("{}", year);
+                    print_frequencies(frequencies, min_length, limit);
+                    println!();
+                }
+            } else {
+                print_frequencies(get_answer_frequencies(&answers), min_length, limit);
+            }
+        },
+        AnswerCommand::Repeaters { min_count, min_length } => {
+            let frequencies = get_answer_frequencies(&tracker.answers()?).into_iter()
+                .filter(|f| f.count >= min_count)
+                .collect::<Vec<_>>();
+            println!("{} answers seen at least {} times", frequencies.len(), min_count);
+            print_frequencies(frequencies, min_length, usize::MAX);
+        },
+        AnswerCommand::CommonFill { min_count, filter } => {
+            let points = tracker.common_fill_points(&filter, min_count)?;
+            let correlation = |points: &[&CommonFillPoint]| {
+                let pairs = points.iter().map(|p| (p.common_share, p.relative_time)).collect::<Vec<_>>();
+                pearson(&pairs).map(|r| format!("{:+.2}", r)).unwrap_or_else(|| "-".to_string())
+            };
+
+            println!("correlation of common-fill share with relative time (negative: common fill speeds us up)");
+            println!("{:<8} {:>6} {:>8}", "", "solves", "r");
+            println!("{:<8} {:>6} {:>8}", "all", points.len(), correlation(&points.iter().collect::<Vec<_>>()));
+            for day in WEEKDAYS.iter() {
+                let day_points = points.iter().filter(|p| p.print_date.weekday() == *day).collect::<Vec<_>>();
+                println!("{:<8} {:>6} {:>8}", day, day_points.len(), correlation(&day_points));
+            }
+
+            let mut sorted = points.iter().collect::<Vec<_>>();
+            sorted.sort_by(|a, b| a.common_share.total_cmp(&b.common_share));
+            println!();
+            println!("{:<15} {:>6} {:>14}", "common share", "solves", "relative time");
+            let quarter = sorted.len().div_ceil(4).max(1);
+            for bucket in sorted.chunks(quarter) {
+                let mean = bucket.iter().map(|p| p.relative_time).sum::<f64>() / bucket.len() as f64;
+                let range = format!("{:.0}-{:.0}%", bucket[0].common_share * 100.0, bucket[bucket.len() - 1].common_share * 100.0);
+                println!("{:<15} {:>6} {:>+13.0}%", range, bucket.len(), (mean - 1.0) * 100.0);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn note(tracker: &Tracker, command: NoteCommand) -> Result<()> {
+    let notes = match command {
+        NoteCommand::Add { date, text, puzzle_type } => {
+            let id = tracker.add_note(date, puzzle_type, &text)?;
+            println!("added note {}", id);
+            return Ok(());
+        },
+        NoteCommand::Delete { id } => {
+            if !tracker.delete_note(id)? {
+                println!("no note with id {}", id);
+            }
+            return Ok(());
+        },
+        NoteCommand::List { date } => tracker.notes(date)?,
+        NoteCommand::Search { query } => tracker.search_notes(&query)?
+    };
+    for note in notes {
+        println!("{:>4}  {} {:<3} {:<5}  {}", note.id, date_to_string(&note.print_date), note.print_date.weekday(), note.puzzle_type, note.text);
+    }
+    Ok(())
+}
+
+fn tag(tracker: &mut Tracker, command: TagCommand) -> Result<()> {
+    match command {
+        TagCommand::Add { date, tags, puzzle_type } => tracker.add_tags(date, puzzle_type, &tags)?,
+        TagCommand::Remove { date, tags, puzzle_type } => tracker.remove_tags(date, puzzle_type, &tags)?,
+        TagCommand::List { tag } => {
+            let mut tagged = tracker.tags()?.into_iter()
+                .filter(|(_, tags)| tag.as_ref().is_none_or(|t| tags.contains(t)))
+                .collect::<Vec<_>>();
+            tagged.sort_by_key(|(key, _)| *key);
+            for ((date, puzzle_type), tags) in tagged {
+                println!("{} {:<3} {:<5}  {}", date_to_string(&date), date.weekday(), puzzle_type, tags.join(", "));
+            }
+        },
+        TagCommand::Stats { filter } => {
+            println!("{:<20} {:>6} {:>7} {:>6} {:>10}", "tag", "count", "solved", "gold", "mean time");
+            for (tag, summary) in tracker.tag_summaries(&filter)? {
+                let mean = summary.mean_time().map(format_duration).unwrap_or_default();
+                println!("{:<20} {:>6} {:>7} {:>6} {:>10}", tag, summary.count, summary.solved, summary.gold, mean);
+            }
+        }
+    }
+    Ok(())
+}
 
+fn outliers(tracker: &mut Tracker, command: OutlierCommand) -> Result<()> {
+    match command {
+        OutlierCommand::Detect { mut rules, dry_run } => {
+            if rules.is_empty() {
+                rules.iqr = Some(3.0);
+            }
+            let detected = tracker.detect_outliers(&rules, dry_run)?;
+            for ((date, puzzle_type), reason) in &detected {
+                println!("{} {:<3} {:<6} {}", date_to_string(date), date.weekday(), puzzle_type.as_str(), reason);
+            }
+            println!("{} {}", detected.len(), if dry_run { "would be flagged" } else { "flagged" });
+        },
+        OutlierCommand::List => {
+            for (xword, outlier) in tracker.outliers()? {
+                let time = match xword.solve_state {
+                    SolveState::Gold { time } => format_duration(time as f64),
+                    _ => String::new()
+                };
+                let status = match (outlier.manual, outlier.flagged) {
+                    (true, true) => "flagged by hand".to_string(),
+                    (true, false) => "kept by hand".to_string(),
+                    (false, _) => outlier.reason
+                };
+                println!("{} {:<3} {:<6} {:>8}  {}", date_to_string(&xword.print_date), xword.print_date.weekday(),
+                    xword.puzzle_type.as_str(), time, status);
+            }
+        },
+        OutlierCommand::Flag { date, puzzle_type } | OutlierCommand::Keep { date, puzzle_type } => {
+            let flagged = matches!(command, OutlierCommand::Flag { .. });
+            if !tracker.set_outlier(date, puzzle_type, flagged)? {
+                return Err(anyhow!("no {} solve on {}", puzzle_type.as_str(), date_to_string(&date)));
+            }
+        },
+        OutlierCommand::Clear { date, puzzle_type } => tracker.clear_outlier(date, puzzle_type)?
+    }
     Ok(())
 }
 
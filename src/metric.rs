@@ -0,0 +1,430 @@
+use crate::stats::{ewma, fit_trend, rolling, Statistic, Window};
+use crate::tracker::{ParseVariantError, SolveState, XwordSummary};
+use crate::util::format_duration;
+
+use chrono::prelude::*;
+use structopt::StructOpt;
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// One line of a metric: a weekday, a year, or everything.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Group {
+    /// Sorts groups, and picks their color.
+    pub order: i32,
+    pub label: String
+}
+
+/// A metric's values for each group, in date order.
+pub type Series = BTreeMap<Group, Vec<(NaiveDate, f64)>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    /// Seconds, shown as m:ss and plotted in minutes.
+    Duration,
+    /// A fraction from 0 to 1, shown as a percentage.
+    Rate
+}
+
+impl Unit {
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            Unit::Duration => format_duration(value),
+            Unit::Rate => format!("{:.0}%", value * 100.0)
+        }
+    }
+
+    /// What a value is divided by to get the plotted figure.
+    pub fn scale(&self) -> f64 {
+        match self {
+            Unit::Duration => 60.0,
+            Unit::Rate => 1.0
+        }
+    }
+
+    /// Formats a plotted figure for an axis label.
+    pub fn axis_label(&self, figure: f64) -> String {
+        match self {
+            Unit::Duration => format!("{}:00", figure as u32),
+            Unit::Rate => format!("{:.0}%", figure * 100.0)
+        }
+    }
+}
+
+/// Which solves go on the same line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grouping {
+    Weekday,
+    Year,
+    All
+}
+
+impl Grouping {
+    pub fn group(&self, xword: &XwordSummary) -> Group {
+        match self {
+            Grouping::Weekday => Group {
+                order: xword.print_date.weekday().num_days_from_monday() as i32,
+                label: xword.print_date.weekday().to_string()
+            },
+            Grouping::Year => Group { order: xword.print_date.year(), label: xword.print_date.year().to_string() },
+            Grouping::All => Group { order: 0, label: "All".to_string() }
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Grouping::Weekday => "Weekday",
+            Grouping::Year => "Year",
+            Grouping::All => "All"
+        }
+    }
+}
+
+impl FromStr for Grouping {
+    type Err = ParseVariantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "weekday" => Ok(Grouping::Weekday),
+            "year" => Ok(Grouping::Year),
+            "all" => Ok(Grouping::All),
+            _ => Err(ParseVariantError::new("grouping", s))
+        }
+    }
+}
+
+/// How a group's raw values are turned into a line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    Rolling(Window, Statistic),
+    Ewma(Window)
+}
+
+impl Smoothing {
+    pub fn apply(&self, points: &[(NaiveDate, f64)], min_samples: Option<usize>) -> Vec<(NaiveDate, f64)> {
+        match self {
+            Smoothing::Rolling(window, statistic) => {
+                let min_samples = min_samples.unwrap_or_else(|| window.default_min_samples());
+                rolling(points, *window, min_samples, |values| statistic.apply(values))
+            },
+            Smoothing::Ewma(half_life) => {
+                let min_samples = min_samples.unwrap_or_else(|| half_life.default_min_samples());
+                ewma(points, *half_life, min_samples)
+            }
+        }
+    }
+}
+
+/// Something measured over solves and charted over time, one line per group.
+/// Implementors say what each solve contributes and how a group's values are
+/// combined; grouping, plotting and exporting are shared.
+pub trait Metric {
+    /// For chart titles and tables, e.g. "30-Puzzle Moving Medians".
+    fn name(&self) -> String;
+
+    /// Names the chart file, e.g. "moving_averages".
+    fn file_stem(&self) -> &'static str;
+
+    fn unit(&self) -> Unit;
+
+    fn grouping(&self) -> Grouping;
+
+    /// What one solve contributes, or None to leave it out.
+    fn value(&self, xword: &XwordSummary) -> Option<f64>;
+
+    /// Turns one group's date-ordered values into the line that's shown.
+    fn compute(&self, points: &[(NaiveDate, f64)]) -> Vec<(NaiveDate, f64)>;
+
+    fn title(&self) -> String {
+        match self.grouping() {
+            Grouping::All => self.name(),
+            grouping => format!("{} by {}", self.name(), grouping.title())
+        }
+    }
+
+    /// A fitted curve to draw behind a group's line, if the metric has one.
+    fn trend(&self, _points: &[(NaiveDate, f64)]) -> Option<Vec<(NaiveDate, f64)>> {
+        None
+    }
+
+    /// Each group's raw values, in date order.
+    fn values(&self, xwords: &[XwordSummary]) -> Series {
+        let mut groups: Series = BTreeMap::new();
+        for xword in xwords {
+            if let Some(value) = self.value(xword) {
+                groups.entry(self.grouping().group(xword)).or_default().push((xword.print_date, value));
+            }
+        }
+        for points in groups.values_mut() {
+            points.sort_by_key(|(date, _)| *date);
+        }
+        groups
+    }
+
+    fn series(&self, xwords: &[XwordSummary]) -> Series {
+        self.values(xwords).into_iter()
+            .map(|(group, points)| (group, self.compute(&points)))
+            .filter(|(_, points)| !points.is_empty())
+            .collect()
+    }
+
+    /// Flagged outliers to mark on the chart. Only a time can be marked; a
+    /// solve's value in a rate is 0 or 1, which says nothing about it.
+    fn flagged(&self, flagged: &[XwordSummary]) -> Series {
+        match self.unit() {
+            Unit::Duration => self.values(flagged),
+            Unit::Rate => Series::new()
+        }
+    }
+
+    fn trends(&self, xwords: &[XwordSummary]) -> Series {
+        self.values(xwords).into_iter()
+            .filter_map(|(group, points)| self.trend(&points).map(|trend| (group, trend)))
+            .collect()
+    }
+}
+
+/// Gold solve times.
+pub struct SolveTimes {
+    pub smoothing: Smoothing,
+    pub grouping: Grouping,
+    pub min_samples: Option<usize>,
+    /// Overlay a log-linear trend; see `stats::fit_trend`. It's fitted against
+    /// print dates, the chart's axis, where `trends` uses solve dates.
+    pub trend: bool
+}
+
+impl Metric for SolveTimes {
+    fn name(&self) -> String {
+        match self.smoothing {
+            Smoothing::Rolling(window, statistic) => format!("{} Moving {}", window.title(), statistic.title()),
+            Smoothing::Ewma(half_life) => format!("Weighted Moving Averages ({} Half-Life)", half_life.title())
+        }
+    }
+
+    fn file_stem(&self) -> &'static str {
+        "moving_averages"
+    }
+
+    fn unit(&self) -> Unit {
+        Unit::Duration
+    }
+
+    fn grouping(&self) -> Grouping {
+        self.grouping
+    }
+
+    fn value(&self, xword: &XwordSummary) -> Option<f64> {
+        match xword.solve_state {
+            SolveState::Gold { time } => Some(time as f64),
+            _ => None
+        }
+    }
+
+    fn compute(&self, points: &[(NaiveDate, f64)]) -> Vec<(NaiveDate, f64)> {
+        self.smoothing.apply(points, self.min_samples)
+    }
+
+    fn trend(&self, points: &[(NaiveDate, f64)]) -> Option<Vec<(NaiveDate, f64)>> {
+        if !self.trend {
+            return None;
+        }
+        let trend = fit_trend(points)?;
+        let (first, last) = (points.first()?.0, points.last()?.0);
+        let days = (last - first).num_days();
+        // Log-linear, so a handful of points draws the curve smoothly enough.
+        Some((0..=20).map(|i| {
+            let date = first + chrono::Duration::days(days * i / 20);
+            (date, trend.at(date))
+        }).collect())
+    }
+}
+
+/// The share of puzzles solved gold.
+pub struct GoldRate {
+    pub smoothing: Smoothing,
+    pub grouping: Grouping,
+    pub min_samples: Option<usize>
+}
+
+impl Metric for GoldRate {
+    fn name(&self) -> String {
+        match self.smoothing {
+            Smoothing::Rolling(window, _) => format!("{} Moving Gold Rates", window.title()),
+            Smoothing::Ewma(half_life) => format!("Weighted Moving Gold Rates ({} Half-Life)", half_life.title())
+        }
+    }
+
+    fn file_stem(&self) -> &'static str {
+        "moving_percentages"
+    }
+
+    fn unit(&self) -> Unit {
+        Unit::Rate
+    }
+
+    fn grouping(&self) -> Grouping {
+        self.grouping
+    }
+
+    fn value(&self, xword: &XwordSummary) -> Option<f64> {
+        match xword.solve_state {
+            SolveState::Gold { .. } => Some(1.0),
+            _ => Some(0.0)
+        }
+    }
+
+    fn compute(&self, points: &[(NaiveDate, f64)]) -> Vec<(NaiveDate, f64)> {
+        self.smoothing.apply(points, self.min_samples)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricKind {
+    Times,
+    GoldRate
+}
+
+impl FromStr for MetricKind {
+    type Err = ParseVariantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "times" => Ok(MetricKind::Times),
+            "gold-rate" | "gold_rate" => Ok(MetricKind::GoldRate),
+            _ => Err(ParseVariantError::new("metric", s))
+        }
+    }
+}
+
+// What the moving-value charts show.
+#[derive(StructOpt, Debug)]
+pub struct PlotOptions {
+    /// What to plot the moving value of: mean, median, a percentile (p90),
+    /// or a trimmed or winsorized mean (trimmed10, winsorized10)
+    #[structopt(long, default_value = "mean")]
+    pub statistic: Statistic,
+
+    /// Window for moving times: a number of puzzles (30), days (90d) or weeks (52w)
+    #[structopt(long, default_value = "30")]
+    pub window: Window,
+
+    /// Window for moving solve rates, in the same form as --window
+    #[structopt(long, default_value = "50")]
+    pub rate_window: Window,
+
+    /// Skip points whose window holds fewer samples than this; defaults to a
+    /// full window of puzzles, or 5 for calendar windows
+    #[structopt(long)]
+    pub min_samples: Option<usize>,
+
+    /// Plot exponentially weighted moving averages instead, whose weights halve
+    /// every this many puzzles (10) or days (30d)
+    #[structopt(long, conflicts_with_all = &["statistic", "window", "rate-window"])]
+    pub half_life: Option<Window>,
+
+    /// One line per weekday, year, or all
+    #[structopt(long, default_value = "weekday")]
+    pub group_by: Grouping,
+
+    /// Overlay a fitted trend on the moving times; see `trends`
+    #[structopt(long)]
+    pub trend: bool
+}
+
+impl PlotOptions {
+    pub fn metric(&self, kind: MetricKind) -> Box<dyn Metric> {
+        match kind {
+            MetricKind::Times => Box::new(SolveTimes {
+                smoothing: self.half_life.map(Smoothing::Ewma).unwrap_or(Smoothing::Rolling(self.window, self.statistic)),
+                grouping: self.group_by,
+                min_samples: self.min_samples,
+                trend: self.trend
+            }),
+            MetricKind::GoldRate => Box::new(GoldRate {
+                smoothing: self.half_life.map(Smoothing::Ewma).unwrap_or(Smoothing::Rolling(self.rate_window, Statistic::Mean)),
+                grouping: self.group_by,
+                min_samples: self.min_samples
+            })
+        }
+    }
+
+    /// The metrics drawn by `plot`.
+    pub fn metrics(&self) -> Vec<Box<dyn Metric>> {
+        vec![self.metric(MetricKind::Times), self.metric(MetricKind::GoldRate)]
+    }
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        PlotOptions {
+            statistic: Statistic::Mean,
+            window: Window::Puzzles(30),
+            rate_window: Window::Puzzles(50),
+            min_samples: None,
+            half_life: None,
+            group_by: Grouping::Weekday,
+            trend: false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::{PuzzleType, SolveSource};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 3, day).unwrap()
+    }
+
+    fn xword(day: u32, solve_state: SolveState) -> XwordSummary {
+        XwordSummary { print_date: date(day), puzzle_type: PuzzleType::Daily, solve_state, source: SolveSource::Api }
+    }
+
+    fn gold(day: u32, time: u32) -> XwordSummary {
+        xword(day, SolveState::Gold { time })
+    }
+
+    fn times(grouping: Grouping, trend: bool) -> SolveTimes {
+        SolveTimes { smoothing: Smoothing::Rolling(Window::Puzzles(2), Statistic::Median), grouping, min_samples: None, trend }
+    }
+
+    fn lines(series: Series) -> Vec<(String, Vec<(NaiveDate, f64)>)> {
+        series.into_iter().map(|(group, points)| (group.label, points)).collect()
+    }
+
+    #[test]
+    fn series_smooths_each_group_and_drops_empty_ones() {
+        // Mondays, Tuesdays and a lone Wednesday, too few for a window of two.
+        let xwords = [
+            gold(1, 600), gold(2, 400), gold(3, 700), gold(8, 300),
+            xword(9, SolveState::Solved), gold(15, 900), gold(16, 500)
+        ];
+        assert_eq!(lines(times(Grouping::Weekday, false).series(&xwords)), [
+            ("Mon".to_string(), vec![(date(8), 450.0), (date(15), 600.0)]),
+            ("Tue".to_string(), vec![(date(16), 450.0)])
+        ]);
+
+        let rate = GoldRate { smoothing: Smoothing::Rolling(Window::Puzzles(2), Statistic::Mean), grouping: Grouping::All, min_samples: None };
+        assert_eq!(lines(rate.series(&xwords)), [
+            ("All".to_string(), vec![(date(2), 1.0), (date(3), 1.0), (date(8), 1.0), (date(9), 0.5), (date(15), 0.5), (date(16), 1.0)])
+        ]);
+        assert!(rate.flagged(&xwords).is_empty());
+        assert_eq!(times(Grouping::All, false).flagged(&xwords[..2]).values().next().unwrap(), &[(date(1), 600.0), (date(2), 400.0)]);
+    }
+
+    #[test]
+    fn trends_follow_a_halving_time_when_asked() {
+        let xwords = [gold(1, 800), gold(8, 400), gold(15, 200)];
+        assert!(times(Grouping::All, false).trends(&xwords).is_empty());
+
+        let trends = times(Grouping::All, true).trends(&xwords);
+        let trend = trends.values().next().unwrap();
+        assert_eq!(trend.len(), 21);
+        let ((first, start), (last, end)) = (trend[0], trend[20]);
+        assert_eq!((first, last), (date(1), date(15)));
+        assert!((start - 800.0).abs() < 1e-6 && (end - 200.0).abs() < 1e-6, "{} to {}", start, end);
+    }
+}
@@ -1,23 +1,50 @@
-use crate::tracker::{SolveState, XwordSummary};
+use crate::puzzle::{CellFill, Clue, Direction, GridCell, Puzzle, PuzzleGrid};
+use crate::tracker::{PuzzleType, SolveSource, SolveState, XwordSummary};
 use crate::util::*;
 
 use chrono::prelude::*;
 use futures::stream::{self, StreamExt, TryStreamExt};
-use reqwest::Client;
+use reqwest::{Client, Response, StatusCode};
 use serde::Deserialize;
 use thiserror::Error;
 
+use std::collections::HashSet;
 use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 struct XwordDetail {
-    calcs: XwordCalc
+    calcs: XwordCalc,
+
+    board: Option<XwordBoard>,
+
+    firsts: Option<XwordFirsts>
+}
+
+// Unix timestamps of the first time something happened to the puzzle.
+#[derive(Deserialize, Debug)]
+struct XwordFirsts {
+    opened: Option<i64>,
+
+    solved: Option<i64>
+}
+
+#[derive(Deserialize, Debug)]
+struct XwordBoard {
+    #[serde(default)]
+    cells: Vec<XwordBoardCell>
+}
+
+#[derive(Deserialize, Debug)]
+struct XwordBoardCell {
+    guess: Option<String>,
+
+    // Seconds into the solve at which the cell was last changed. Older games
+    // don't have it.
+    timestamp: Option<u32>
 }
 
 #[derive(Deserialize, Debug)]
 struct XwordCalc {
-    solved: bool,
-    
     #[serde(rename="secondsSpentSolving")]
     seconds_spent_solving: u32
 }
@@ -35,6 +62,102 @@ struct XwordList {
     results: Vec<XwordSummaryInternal>
 }
 
+#[derive(Deserialize, Debug)]
+struct PuzzleDetail {
+    id: u32,
+
+    #[serde(rename="publicationDate")]
+    publication_date: String,
+
+    title: Option<String>,
+
+    #[serde(default)]
+    constructors: Vec<String>,
+
+    editor: Option<String>,
+
+    #[serde(default)]
+    notes: Vec<PuzzleNote>,
+
+    body: Vec<PuzzleBody>
+}
+
+#[derive(Deserialize, Debug)]
+struct PuzzleNote {
+    text: String
+}
+
+#[derive(Deserialize, Debug)]
+struct PuzzleBody {
+    dimensions: PuzzleDimensions,
+
+    // Black squares come through as empty objects.
+    #[serde(default)]
+    cells: Vec<PuzzleCell>,
+
+    #[serde(default)]
+    clues: Vec<PuzzleClue>
+}
+
+#[derive(Deserialize, Debug)]
+struct PuzzleDimensions {
+    width: u32,
+    height: u32
+}
+
+#[derive(Deserialize, Debug)]
+struct PuzzleCell {
+    answer: Option<String>,
+
+    label: Option<String>,
+
+    #[serde(rename="type", default)]
+    cell_type: u32
+}
+
+// Cell types used by the puzzle endpoint.
+const CIRCLED_CELL: u32 = 2;
+const SHADED_CELL: u32 = 3;
+
+#[derive(Deserialize, Debug)]
+struct PuzzleClue {
+    direction: String,
+
+    label: String,
+
+    #[serde(default)]
+    text: Vec<PuzzleClueText>,
+
+    #[serde(default)]
+    cells: Vec<usize>
+}
+
+#[derive(Deserialize, Debug)]
+struct PuzzleClueText {
+    plain: Option<String>
+}
+
+/// A puzzle's solve state as fetched from the NYTimes, along with the id
+/// needed to look up anything else about it.
+#[derive(Debug)]
+pub struct SyncedXword {
+    pub puzzle_id: u32,
+    pub summary: XwordSummary,
+    /// When each cell was filled, for solved puzzles whose game records it.
+    pub cell_fills: Vec<CellFill>,
+    /// When the puzzle was first opened, for solved puzzles.
+    pub opened_at: Option<DateTime<Utc>>,
+    /// When the puzzle was first solved, if it has been.
+    pub solved_at: Option<DateTime<Utc>>
+}
+
+/// The puzzles fetched from a batch, and why each of the others couldn't be.
+#[derive(Debug, Default)]
+pub struct FetchedPuzzles {
+    pub puzzles: Vec<(Puzzle, PuzzleGrid)>,
+    pub failed: Vec<(u32, NYTimesError)>
+}
+
 pub struct NYTimes {
     session: String,
     client: Client
@@ -43,13 +166,19 @@ pub struct NYTimes {
 #[derive(Error, Debug)]
 pub enum NYTimesError {
     #[error("Invalid session token provided")]
-    InvalidSessionError,
+    InvalidSession,
+
+    #[error("Puzzle {0} has no body")]
+    EmptyPuzzle(u32),
+
+    #[error("{1} returned {0}")]
+    Http(reqwest::StatusCode, String),
 
     #[error(transparent)]
-    ReqwestError(#[from] reqwest::Error),
+    Reqwest(#[from] reqwest::Error),
 
     #[error(transparent)]
-    DateError(#[from] chrono::ParseError)
+    Date(#[from] chrono::ParseError)
 }
 
 impl NYTimes {
@@ -64,7 +193,41 @@ impl NYTimes {
         })
     }
 
-    pub async fn get_all_times(&self, start_date: Date<Utc>, end_date: Date<Utc>) -> Result<Vec<XwordSummary>, NYTimesError> {
+    /// Fetches the solves in the range. Games in `synced` have already been
+    /// stored, so they're only fetched again for a gold time.
+    pub async fn get_all_times(&self, start_date: NaiveDate, end_date: NaiveDate, synced: &HashSet<u32>) -> Result<Vec<SyncedXword>, NYTimesError> {
+        let mut time_futs = Vec::new();
+        self.get_all_history(start_date, end_date).await?.into_iter().for_each(|xword| {
+            let fetch_game = xword.star.is_some() || !synced.contains(&xword.puzzle_id);
+            time_futs.push(self.process_xword_summary(xword, fetch_game));
+        });
+        
+        stream::iter(time_futs).buffer_unordered(10).try_collect::<Vec<_>>().await
+    }
+
+    /// Returns the publication date and id of every puzzle in the range,
+    /// without fetching any solve details.
+    pub async fn get_puzzle_ids(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<(NaiveDate, u32)>, NYTimesError> {
+        Ok(self.get_all_history(start_date, end_date).await?.into_iter()
+            .map(|xword| (string_to_date(&xword.print_date), xword.puzzle_id))
+            .collect())
+    }
+
+    /// Fetches each puzzle, carrying on past any that fail so that one bad
+    /// puzzle doesn't hold up the rest.
+    pub async fn get_puzzles(&self, ids: &[u32]) -> FetchedPuzzles {
+        let puzzle_futs = ids.iter().map(|id| async move { (*id, self.get_puzzle(*id).await) });
+        let mut fetched = FetchedPuzzles::default();
+        for (id, result) in stream::iter(puzzle_futs).buffer_unordered(10).collect::<Vec<_>>().await {
+            match result {
+                Ok(puzzle) => fetched.puzzles.push(puzzle),
+                Err(e) => fetched.failed.push((id, e))
+            }
+        }
+        fetched
+    }
+
+    async fn get_all_history(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<XwordSummaryInternal>, NYTimesError> {
         let mut curr = start_date;
         let mut history_futs = Vec::new();
 
@@ -74,52 +237,127 @@ impl NYTimes {
             curr = next;
         }
 
-        let mut time_futs = Vec::new();
-        stream::iter(history_futs).buffer_unordered(10).try_collect::<Vec<_>>().await?.into_iter().flatten().for_each(|xword| {
-            time_futs.push(self.process_xword_summary(xword));
-        });
-        
-        Ok(stream::iter(time_futs).buffer_unordered(10).try_collect::<Vec<_>>().await?)
+        Ok(stream::iter(history_futs).buffer_unordered(10).try_collect::<Vec<_>>().await?.into_iter().flatten().collect())
     }
 
     async fn get_history(&self, start_date: String, end_date: String) -> Result<Vec<XwordSummaryInternal>, NYTimesError> {
         println!("getting history from {}", start_date);
         let url = format!("http://nyt-games-prd.appspot.com/svc/crosswords/v3/50657393/puzzles.json?publish_type=daily&date_start={}&date_end={}", start_date, end_date);
-        let response = self.client.get(&url).header("nyt-s", &self.session).send().await?;
+        let response = check_status(self.client.get(&url).header("nyt-s", &self.session).send().await?)?;
         let xword_list = response.json::<XwordList>().await?;
         println!("got history for {}", start_date);
         Ok(xword_list.results)
     }
 
-    async fn process_xword_summary(&self, xword: XwordSummaryInternal) -> Result<XwordSummary, NYTimesError> {
+    async fn process_xword_summary(&self, xword: XwordSummaryInternal, fetch_game: bool) -> Result<SyncedXword, NYTimesError> {
         println!("getting time for {} on {}", xword.puzzle_id, xword.print_date);
-        let solve_state = if xword.solved {
-            match xword.star {
-                Some(_) => SolveState::Gold { 
-                    time: self.get_xword_time(xword.puzzle_id).await?.expect(&format!("Missing time for {} xword", xword.print_date)) 
-                },
-                None => SolveState::Solved
-            }
+        let game = if xword.solved && fetch_game {
+            Some(self.get_game(xword.puzzle_id).await?)
         } else {
-            SolveState::Unsolved
+            None
+        };
+        let solve_state = match (xword.solved, &game, &xword.star) {
+            (true, Some(game), Some(_)) => SolveState::Gold { time: game.calcs.seconds_spent_solving },
+            (true, _, _) => SolveState::Solved,
+            (false, _, _) => SolveState::Unsolved
         };
+        let firsts = game.as_ref().and_then(|g| g.firsts.as_ref());
 
-        Ok(XwordSummary {
-            print_date: string_to_date(&xword.print_date),
-            solve_state: solve_state
+        Ok(SyncedXword {
+            puzzle_id: xword.puzzle_id,
+            summary: XwordSummary {
+                print_date: string_to_date(&xword.print_date),
+                puzzle_type: PuzzleType::Daily,
+                solve_state,
+                source: SolveSource::Api
+            },
+            cell_fills: game.as_ref().map(cell_fills_from_game).unwrap_or_default(),
+            opened_at: firsts.and_then(|f| f.opened).and_then(|t| DateTime::from_timestamp(t, 0)),
+            solved_at: firsts.and_then(|f| f.solved).and_then(|t| DateTime::from_timestamp(t, 0))
         })
     }
 
-    async fn get_xword_time(&self, id: u32) -> Result<Option<u32>, NYTimesError> {
+    async fn get_game(&self, id: u32) -> Result<XwordDetail, NYTimesError> {
         let url = format!("https://nyt-games-prd.appspot.com/svc/crosswords/v6/game/{}.json", id);
-        let response = self.client.get(&url).header("nyt-s", &self.session).send().await?;
+        let response = check_status(self.client.get(&url).header("nyt-s", &self.session).send().await?)?;
         println!("got response for {}", id);
-        if response.status() == reqwest::StatusCode::OK {
-            let json = response.json::<XwordDetail>().await?;
-            Ok(Some(json.calcs.seconds_spent_solving))
-        } else {
-            Err(NYTimesError::InvalidSessionError)
+        Ok(response.json::<XwordDetail>().await?)
+    }
+
+    async fn get_puzzle(&self, id: u32) -> Result<(Puzzle, PuzzleGrid), NYTimesError> {
+        let detail = self.get_puzzle_detail(id).await?;
+        Ok((puzzle_from_detail(&detail)?, grid_from_detail(&detail)))
+    }
+
+    async fn get_puzzle_detail(&self, id: u32) -> Result<PuzzleDetail, NYTimesError> {
+        let url = format!("https://www.nytimes.com/svc/crosswords/v6/puzzle/{}.json", id);
+        let response = check_status(self.client.get(&url).header("nyt-s", &self.session).send().await?)?;
+        let detail = response.json::<PuzzleDetail>().await?;
+        if detail.body.is_empty() {
+            return Err(NYTimesError::EmptyPuzzle(id));
         }
+        Ok(detail)
+    }
+}
+
+/// Only 401 and 403 mean the session is bad; anything else unsuccessful is
+/// reported as it is.
+fn check_status(response: Response) -> Result<Response, NYTimesError> {
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(NYTimesError::InvalidSession),
+        status if !status.is_success() => Err(NYTimesError::Http(status, response.url().to_string())),
+        _ => Ok(response)
     }
+}
+
+fn cell_fills_from_game(game: &XwordDetail) -> Vec<CellFill> {
+    let cells = match &game.board {
+        Some(board) => &board.cells,
+        None => return Vec::new()
+    };
+    cells.iter().enumerate().filter_map(|(cell, c)| match (&c.guess, c.timestamp) {
+        (Some(guess), Some(timestamp)) if !guess.is_empty() => Some(CellFill { cell, guess: guess.clone(), timestamp }),
+        _ => None
+    }).collect()
+}
+
+fn puzzle_from_detail(detail: &PuzzleDetail) -> Result<Puzzle, NYTimesError> {
+    let body = &detail.body[0];
+    let notes = detail.notes.iter().map(|n| n.text.as_str()).collect::<Vec<_>>();
 
-}
\ No newline at end of file
+    Ok(Puzzle {
+        puzzle_id: detail.id,
+        print_date: parse_date(&detail.publication_date)?,
+        puzzle_type: PuzzleType::Daily,
+        title: detail.title.clone().filter(|t| !t.is_empty()),
+        constructors: detail.constructors.clone(),
+        editor: detail.editor.clone(),
+        width: body.dimensions.width,
+        height: body.dimensions.height,
+        has_rebus: body.cells.iter().any(|c| c.answer.as_ref().is_some_and(|a| a.chars().count() > 1)),
+        notes: if notes.is_empty() { None } else { Some(notes.join("\n")) }
+    })
+}
+
+fn grid_from_detail(detail: &PuzzleDetail) -> PuzzleGrid {
+    let body = &detail.body[0];
+    let cells = body.cells.iter().map(|c| GridCell {
+        answer: c.answer.clone(),
+        label: c.label.clone(),
+        circled: c.cell_type == CIRCLED_CELL,
+        shaded: c.cell_type == SHADED_CELL
+    }).collect();
+    let clues = body.clues.iter().map(|c| Clue {
+        direction: if c.direction == "Down" { Direction::Down } else { Direction::Across },
+        label: c.label.clone(),
+        text: c.text.iter().filter_map(|t| t.plain.as_deref()).collect::<Vec<_>>().join(" "),
+        cells: c.cells.clone()
+    }).collect();
+
+    PuzzleGrid {
+        width: body.dimensions.width,
+        height: body.dimensions.height,
+        cells,
+        clues
+    }
+}
@@ -0,0 +1,367 @@
+use crate::puzzle::{Direction, FilledPuzzle};
+
+use chrono::prelude::*;
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Error, Debug)]
+pub enum PuzFileError {
+    #[error("Unknown puzzle file format: {0}")]
+    UnknownFormat(String),
+
+    #[error("Grid of {0}x{1} is too large for a .puz file")]
+    GridTooLarge(u32, u32),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PuzFileFormat {
+    Ipuz,
+    Puz
+}
+
+impl PuzFileFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PuzFileFormat::Ipuz => "ipuz",
+            PuzFileFormat::Puz => "puz"
+        }
+    }
+}
+
+impl FromStr for PuzFileFormat {
+    type Err = PuzFileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ipuz" => Ok(PuzFileFormat::Ipuz),
+            "puz" => Ok(PuzFileFormat::Puz),
+            _ => Err(PuzFileError::UnknownFormat(s.to_string()))
+        }
+    }
+}
+
+pub fn write_puzzle<W: Write>(filled: &FilledPuzzle, format: PuzFileFormat, writer: W) -> Result<(), PuzFileError> {
+    match format {
+        PuzFileFormat::Ipuz => write_ipuz(filled, writer),
+        PuzFileFormat::Puz => write_puz(filled, writer)
+    }
+}
+
+fn author(filled: &FilledPuzzle) -> String {
+    let mut author = filled.puzzle.constructors.join(" & ");
+    if let Some(editor) = &filled.puzzle.editor {
+        author = format!("{} / {}", author, editor);
+    }
+    author
+}
+
+fn copyright(filled: &FilledPuzzle) -> String {
+    format!("\u{a9} {} The New York Times", filled.puzzle.print_date.year())
+}
+
+// Labels are numeric for every NYT puzzle, but fall back to the raw string
+// rather than lose one.
+fn label_value(label: &str) -> Value {
+    label.parse::<u32>().map(Value::from).unwrap_or_else(|_| Value::from(label))
+}
+
+/// Writes the puzzle as ipuz (http://ipuz.org), with our fill as the saved state.
+pub fn write_ipuz<W: Write>(filled: &FilledPuzzle, mut writer: W) -> Result<(), PuzFileError> {
+    let grid = &filled.grid;
+    let rows = |cell: &dyn Fn(usize) -> Value| -> Value {
+        (0..grid.height as usize).map(|row| {
+            (0..grid.width as usize).map(|col| cell(row * grid.width as usize + col)).collect::<Value>()
+        }).collect()
+    };
+
+    let puzzle = rows(&|i| {
+        let cell = &grid.cells[i];
+        if cell.answer.is_none() {
+            return json!("#");
+        }
+        let label = cell.label.as_deref().map(label_value).unwrap_or_else(|| json!(0));
+        match (cell.circled, cell.shaded) {
+            (false, false) => label,
+            (true, false) => json!({ "cell": label, "style": { "shapebg": "circle" } }),
+            (false, true) => json!({ "cell": label, "style": { "highlight": true } }),
+            (true, true) => json!({ "cell": label, "style": { "shapebg": "circle", "highlight": true } })
+        }
+    });
+    let solution = rows(&|i| match &grid.cells[i].answer {
+        Some(answer) => json!(answer),
+        None => json!("#")
+    });
+    let saved = rows(&|i| match (&grid.cells[i].answer, &filled.fill[i]) {
+        (None, _) => json!("#"),
+        (Some(_), Some(guess)) => json!(guess),
+        (Some(_), None) => json!("")
+    });
+
+    let mut clues = Map::new();
+    for direction in &[Direction::Across, Direction::Down] {
+        let list = grid.sorted_clues().into_iter()
+            .filter(|c| c.direction == *direction)
+            .map(|c| json!([label_value(&c.label), c.text]))
+            .collect::<Value>();
+        clues.insert(direction.as_str().to_string(), list);
+    }
+
+    let mut ipuz = json!({
+        "version": "http://ipuz.org/v2",
+        "kind": ["http://ipuz.org/crossword#1"],
+        "publisher": "The New York Times",
+        "copyright": copyright(filled),
+        "author": author(filled),
+        "date": filled.puzzle.print_date.format("%m/%d/%Y").to_string(),
+        "dimensions": { "width": grid.width, "height": grid.height },
+        "block": "#",
+        "empty": 0,
+        "puzzle": puzzle,
+        "solution": solution,
+        "saved": saved,
+        "clues": clues
+    });
+    if let Some(title) = &filled.puzzle.title {
+        ipuz["title"] = json!(title);
+    }
+    if let Some(editor) = &filled.puzzle.editor {
+        ipuz["editor"] = json!(editor);
+    }
+    if let Some(notes) = &filled.puzzle.notes {
+        ipuz["notes"] = json!(notes);
+    }
+
+    serde_json::to_writer_pretty(&mut writer, &ipuz)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+// .puz strings are ISO-8859-1.
+fn latin1(s: &str) -> Vec<u8> {
+    s.chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }).collect()
+}
+
+fn first_letter(s: &str) -> u8 {
+    latin1(&s.to_uppercase()).first().copied().unwrap_or(b'-')
+}
+
+fn checksum(data: &[u8], mut sum: u16) -> u16 {
+    for byte in data {
+        sum = sum.rotate_right(1).wrapping_add(*byte as u16);
+    }
+    sum
+}
+
+fn with_nul(s: &[u8]) -> Vec<u8> {
+    let mut s = s.to_vec();
+    s.push(0);
+    s
+}
+
+// Checksum over the strings section, which skips empty title/author/copyright
+// /notes and leaves the terminator off each clue.
+fn text_checksum(title: &[u8], author: &[u8], copyright: &[u8], clues: &[Vec<u8>], notes: &[u8], mut sum: u16) -> u16 {
+    for s in &[title, author, copyright] {
+        if !s.is_empty() {
+            sum = checksum(&with_nul(s), sum);
+        }
+    }
+    for clue in clues {
+        sum = checksum(clue, sum);
+    }
+    if !notes.is_empty() {
+        sum = checksum(&with_nul(notes), sum);
+    }
+    sum
+}
+
+fn write_section<W: Write>(writer: &mut W, name: &[u8; 4], data: &[u8]) -> Result<(), PuzFileError> {
+    writer.write_all(name)?;
+    writer.write_all(&(data.len() as u16).to_le_bytes())?;
+    writer.write_all(&checksum(data, 0).to_le_bytes())?;
+    writer.write_all(data)?;
+    writer.write_all(&[0])?;
+    Ok(())
+}
+
+/// Writes the puzzle in Across Lite's binary .puz format (version 1.3), with
+/// our fill as the player state. Rebus squares and circles go in the GRBS/RTBL
+/// and GEXT extra sections; .puz has no way to mark shaded squares.
+pub fn write_puz<W: Write>(filled: &FilledPuzzle, mut writer: W) -> Result<(), PuzFileError> {
+    let grid = &filled.grid;
+    if grid.width > 255 || grid.height > 255 {
+        return Err(PuzFileError::GridTooLarge(grid.width, grid.height));
+    }
+
+    let solution = grid.cells.iter().map(|c| c.answer.as_deref().map(first_letter).unwrap_or(b'.')).collect::<Vec<_>>();
+    let state = grid.cells.iter().zip(&filled.fill).map(|(cell, guess)| match (&cell.answer, guess) {
+        (None, _) => b'.',
+        (Some(_), Some(guess)) => first_letter(guess),
+        (Some(_), None) => b'-'
+    }).collect::<Vec<_>>();
+
+    let title = latin1(filled.puzzle.title.as_deref().unwrap_or(""));
+    let author = latin1(&author(filled));
+    let copyright = latin1(&copyright(filled));
+    let notes = latin1(filled.puzzle.notes.as_deref().unwrap_or(""));
+    let clues = grid.sorted_clues().into_iter().map(|c| latin1(&c.text)).collect::<Vec<_>>();
+
+    let mut header = [0u8; 0x34];
+    header[0x02..0x0e].copy_from_slice(b"ACROSS&DOWN\0");
+    header[0x18..0x1c].copy_from_slice(b"1.3\0");
+    header[0x2c] = grid.width as u8;
+    header[0x2d] = grid.height as u8;
+    header[0x2e..0x30].copy_from_slice(&(clues.len() as u16).to_le_bytes());
+    header[0x30..0x32].copy_from_slice(&1u16.to_le_bytes());
+
+    let cib = checksum(&header[0x2c..0x34], 0);
+    let solution_sum = checksum(&solution, 0);
+    let state_sum = checksum(&state, 0);
+    let text_sum = text_checksum(&title, &author, &copyright, &clues, &notes, 0);
+    let overall = text_checksum(&title, &author, &copyright, &clues, &notes, checksum(&state, checksum(&solution, cib)));
+
+    header[0x00..0x02].copy_from_slice(&overall.to_le_bytes());
+    header[0x0e..0x10].copy_from_slice(&cib.to_le_bytes());
+    let sums = [cib, solution_sum, state_sum, text_sum];
+    for (i, sum) in sums.iter().enumerate() {
+        header[0x10 + i] = b"ICHE"[i] ^ (*sum & 0xff) as u8;
+        header[0x14 + i] = b"ATED"[i] ^ (*sum >> 8) as u8;
+    }
+
+    writer.write_all(&header)?;
+    writer.write_all(&solution)?;
+    writer.write_all(&state)?;
+    for s in &[&title, &author, &copyright] {
+        writer.write_all(&with_nul(s))?;
+    }
+    for clue in &clues {
+        writer.write_all(&with_nul(clue))?;
+    }
+    writer.write_all(&with_nul(&notes))?;
+
+    if filled.puzzle.has_rebus {
+        let mut keys: HashMap<&str, u8> = HashMap::new();
+        let mut table = Vec::new();
+        let mut rebus = Vec::new();
+        for cell in &grid.cells {
+            match cell.answer.as_deref().filter(|a| a.chars().count() > 1) {
+                Some(answer) => {
+                    let next = keys.len() as u8;
+                    let key = *keys.entry(answer).or_insert_with(|| {
+                        table.extend(latin1(&format!("{:>2}:{};", next, answer)));
+                        next
+                    });
+                    rebus.push(key + 1);
+                },
+                None => rebus.push(0)
+            }
+        }
+        write_section(&mut writer, b"GRBS", &rebus)?;
+        write_section(&mut writer, b"RTBL", &table)?;
+    }
+    if grid.cells.iter().any(|c| c.circled) {
+        let markup = grid.cells.iter().map(|c| if c.circled { 0x80 } else { 0 }).collect::<Vec<_>>();
+        write_section(&mut writer, b"GEXT", &markup)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle::{Clue, GridCell, Puzzle, PuzzleGrid};
+    use crate::tracker::PuzzleType;
+
+    fn cell(answer: Option<&str>, label: Option<&str>) -> GridCell {
+        GridCell { answer: answer.map(str::to_string), label: label.map(str::to_string), circled: false, shaded: false }
+    }
+
+    fn clue(direction: Direction, label: &str, text: &str, cells: &[usize]) -> Clue {
+        Clue { direction, label: label.to_string(), text: text.to_string(), cells: cells.to_vec() }
+    }
+
+    // CAT / A#O / BED, solved but for the last square.
+    fn filled_puzzle() -> FilledPuzzle {
+        let letters = ["C", "A", "T", "A", "", "O", "B", "E", "D"];
+        let labels = [Some("1"), None, Some("2"), None, None, None, Some("3"), None, None];
+        let cells = letters.iter().zip(&labels)
+            .map(|(letter, label)| cell(Some(*letter).filter(|l| !l.is_empty()), *label))
+            .collect::<Vec<_>>();
+        let mut fill = cells.iter().map(|c| c.answer.clone()).collect::<Vec<_>>();
+        fill[8] = None;
+        FilledPuzzle {
+            puzzle: Puzzle {
+                puzzle_id: 1,
+                print_date: NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(),
+                puzzle_type: PuzzleType::Daily,
+                title: Some("Test".to_string()),
+                constructors: vec!["Ann Author".to_string()],
+                editor: None,
+                width: 3,
+                height: 3,
+                has_rebus: false,
+                notes: None
+            },
+            grid: PuzzleGrid {
+                width: 3,
+                height: 3,
+                cells,
+                clues: vec![
+                    clue(Direction::Across, "3", "Place to sleep", &[6, 7, 8]),
+                    clue(Direction::Down, "2", "Snooze", &[2, 5, 8]),
+                    clue(Direction::Across, "1", "Feline", &[0, 1, 2]),
+                    clue(Direction::Down, "1", "Taxi", &[0, 3, 6])
+                ]
+            },
+            fill
+        }
+    }
+
+    #[test]
+    fn checksum_rotates_before_adding() {
+        assert_eq!(checksum(b"A", 0), 0x0041);
+        // 0x0041 rotates right to 0x8020 before the B is added.
+        assert_eq!(checksum(b"AB", 0), 0x8062);
+        assert_eq!(checksum(b"ACROSS&DOWN\0", 0), 0x932e);
+    }
+
+    #[test]
+    fn text_checksum_skips_empty_strings() {
+        let clues = vec![b"Feline".to_vec()];
+        assert_eq!(text_checksum(b"", b"", b"", &clues, b"", 0), checksum(b"Feline", 0));
+        assert_eq!(text_checksum(b"Test", b"", b"", &clues, b"", 0), checksum(b"Feline", checksum(b"Test\0", 0)));
+    }
+
+    #[test]
+    fn puz_checksums_match_reference() {
+        let mut bytes = Vec::new();
+        write_puz(&filled_puzzle(), &mut bytes).unwrap();
+
+        // Computed independently from the published .puz format for this puzzle.
+        assert_eq!(&bytes[0x00..0x02], &0xa14au16.to_le_bytes());
+        assert_eq!(&bytes[0x0e..0x10], &0x5200u16.to_le_bytes());
+        assert_eq!(&bytes[0x10..0x14], &[73, 196, 56, 209]);
+        assert_eq!(&bytes[0x14..0x18], &[19, 137, 152, 133]);
+
+        assert_eq!(&bytes[0x34..0x3d], b"CATA.OBED");
+        assert_eq!(&bytes[0x3d..0x46], b"CATA.OBE-");
+        assert_eq!(&bytes[0x46..], &b"Test\0Ann Author\0\xa9 2021 The New York Times\0Feline\0Taxi\0Snooze\0Place to sleep\0\0"[..]);
+    }
+
+    #[test]
+    fn puz_rejects_oversized_grids() {
+        let mut filled = filled_puzzle();
+        filled.grid.width = 300;
+        assert!(matches!(write_puz(&filled, Vec::new()), Err(PuzFileError::GridTooLarge(300, 3))));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::tracker::{ParseVariantError, PuzzleType, SolveState};
+use crate::util::serialize_date;
+
+use chrono::prelude::*;
+use serde::Serialize;
+
+use std::str::FromStr;
+
+/// What we know about a puzzle itself, independent of how we solved it.
+#[derive(Serialize, Debug, Clone)]
+pub struct Puzzle {
+    pub puzzle_id: u32,
+    #[serde(serialize_with = "serialize_date")]
+    pub print_date: NaiveDate,
+    pub puzzle_type: PuzzleType,
+    pub title: Option<String>,
+    pub constructors: Vec<String>,
+    pub editor: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub has_rebus: bool,
+    pub notes: Option<String>
+}
+
+impl Puzzle {
+    pub fn size(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+}
+
+/// One appearance of an answer in a puzzle.
+#[derive(Debug, Clone)]
+pub struct AnswerUse {
+    pub print_date: NaiveDate,
+    pub puzzle_type: PuzzleType,
+    pub answer: String,
+    pub clue: String
+}
+
+/// A clue whose text or answer matched a search, with how we did on its puzzle.
+#[derive(Serialize, Debug, Clone)]
+pub struct ClueMatch {
+    #[serde(serialize_with = "serialize_date")]
+    pub print_date: NaiveDate,
+    pub puzzle_type: PuzzleType,
+    pub direction: Direction,
+    pub label: String,
+    pub text: String,
+    pub answer: String,
+    pub solve_state: SolveState
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction {
+    Across,
+    Down
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Across => "Across",
+            Direction::Down => "Down"
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ParseVariantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "across" | "a" => Ok(Direction::Across),
+            "down" | "d" => Ok(Direction::Down),
+            _ => Err(ParseVariantError::new("direction", s))
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GridCell {
+    /// None for black squares. Rebus squares hold more than one letter.
+    pub answer: Option<String>,
+    pub label: Option<String>,
+    pub circled: bool,
+    pub shaded: bool
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Clue {
+    pub direction: Direction,
+    pub label: String,
+    pub text: String,
+    /// Indices into the grid's cells, in reading order.
+    pub cells: Vec<usize>
+}
+
+/// A puzzle's grid and clues, row-major.
+#[derive(Serialize, Debug, Clone)]
+pub struct PuzzleGrid {
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<GridCell>,
+    pub clues: Vec<Clue>
+}
+
+impl PuzzleGrid {
+    /// The answer to a clue, read off the grid.
+    pub fn answer(&self, clue: &Clue) -> String {
+        clue.cells.iter().filter_map(|i| self.cells[*i].answer.as_deref()).collect()
+    }
+
+    /// Clues in the conventional order: by number, across before down.
+    pub fn sorted_clues(&self) -> Vec<&Clue> {
+        let mut clues = self.clues.iter().collect::<Vec<_>>();
+        clues.sort_by_key(|c| (c.label.parse::<u32>().unwrap_or(u32::MAX), c.direction));
+        clues
+    }
+}
+
+/// When we filled a cell, in seconds into the solve.
+#[derive(Serialize, Debug, Clone)]
+pub struct CellFill {
+    pub cell: usize,
+    pub guess: String,
+    pub timestamp: u32
+}
+
+/// A puzzle together with what we filled into its grid.
+#[derive(Debug, Clone)]
+pub struct FilledPuzzle {
+    pub puzzle: Puzzle,
+    pub grid: PuzzleGrid,
+    /// Our entry for each cell, parallel to the grid's cells.
+    pub fill: Vec<Option<String>>
+}
@@ -0,0 +1,165 @@
+use crate::chart::shade;
+use crate::puzzle::{CellFill, PuzzleGrid};
+use crate::util::format_duration;
+
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use thiserror::Error;
+
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+static CELL_SIZE: i32 = 36;
+static MARGIN: i32 = 10;
+static CAPTION_HEIGHT: i32 = 40;
+
+// Long solves are bucketed so a replay stays a watchable length.
+static MAX_FRAMES: usize = 300;
+static FRAME_DELAY_MS: u32 = 100;
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("Unknown replay format: {0}")]
+    UnknownFormat(String)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReplayFormat {
+    /// A single animated GIF.
+    Gif,
+    /// A directory of numbered PNGs, one per frame.
+    Frames
+}
+
+impl FromStr for ReplayFormat {
+    type Err = ReplayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gif" => Ok(ReplayFormat::Gif),
+            "frames" | "png" => Ok(ReplayFormat::Frames),
+            _ => Err(ReplayError::UnknownFormat(s.to_string()))
+        }
+    }
+}
+
+/// The solve times at which to draw a frame: every distinct fill time, or
+/// evenly spaced buckets if there are too many of those.
+fn frame_times(fills: &[CellFill]) -> Vec<u32> {
+    let mut times = fills.iter().map(|f| f.timestamp).collect::<Vec<_>>();
+    times.sort_unstable();
+    times.dedup();
+    if times.len() > MAX_FRAMES {
+        let end = times.last().copied().unwrap();
+        let step = end.div_ceil(MAX_FRAMES as u32);
+        times = (1..=MAX_FRAMES as u32).map(|i| (i * step).min(end)).collect();
+        times.dedup();
+    }
+    if times.first() != Some(&0) {
+        times.insert(0, 0);
+    }
+    times
+}
+
+fn draw_frame<DB: DrawingBackend>(root: &DrawingArea<DB, plotters::coord::Shift>, grid: &PuzzleGrid, fills: &[CellFill], previous: u32, time: u32) {
+    root.fill(&WHITE).expect("Failed to fill.");
+
+    let label_style = TextStyle::from(("sans-serif", 10).into_font()).pos(Pos::new(HPos::Left, VPos::Top));
+    let letter_style = TextStyle::from(("sans-serif", 22).into_font()).pos(Pos::new(HPos::Center, VPos::Center));
+
+    for (i, cell) in grid.cells.iter().enumerate() {
+        let x = MARGIN + (i as i32 % grid.width as i32) * CELL_SIZE;
+        let y = MARGIN + (i as i32 / grid.width as i32) * CELL_SIZE;
+        let corners = [(x, y), (x + CELL_SIZE, y + CELL_SIZE)];
+        if cell.answer.is_none() {
+            root.draw(&Rectangle::new(corners, BLACK.filled())).expect("Failed to draw cell.");
+            continue;
+        }
+        let fill = fills.iter().find(|f| f.cell == i && f.timestamp <= time);
+        if fill.is_some_and(|f| f.timestamp > previous) {
+            root.draw(&Rectangle::new(corners, shade(200))).expect("Failed to draw cell.");
+        }
+        root.draw(&Rectangle::new(corners, &BLACK)).expect("Failed to draw cell.");
+        if let Some(label) = &cell.label {
+            root.draw(&Text::new(label.as_str(), (x + 2, y + 1), label_style.clone())).expect("Failed to draw label.");
+        }
+        if let Some(fill) = fill {
+            root.draw(&Text::new(fill.guess.as_str(), (x + CELL_SIZE / 2, y + CELL_SIZE / 2 + 3), letter_style.clone()))
+                .expect("Failed to draw letter.");
+        }
+    }
+
+    let caption_y = MARGIN * 2 + grid.height as i32 * CELL_SIZE;
+    let filled = fills.iter().filter(|f| f.timestamp <= time).count();
+    root.draw(&Text::new(
+        format!("{}  ({} cells)", format_duration(time as f64), filled),
+        (MARGIN, caption_y),
+        ("sans-serif", 20).into_font()
+    )).expect("Failed to draw caption.");
+}
+
+/// Draws the order in which cells were filled, one frame per fill time, with
+/// the cells filled since the last frame highlighted. Returns the files
+/// written.
+pub fn write_replay(grid: &PuzzleGrid, fills: &[CellFill], format: ReplayFormat, output: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let size = (
+        (MARGIN * 2 + grid.width as i32 * CELL_SIZE) as u32,
+        (MARGIN * 2 + grid.height as i32 * CELL_SIZE + CAPTION_HEIGHT) as u32
+    );
+    let times = frame_times(fills);
+    let previous = std::iter::once(0).chain(times.iter().copied());
+
+    match format {
+        ReplayFormat::Gif => {
+            if let Some(parent) = output.parent() {
+                create_dir_all(parent)?;
+            }
+            let root = BitMapBackend::gif(output, size, FRAME_DELAY_MS).expect("Failed to create GIF.").into_drawing_area();
+            for (previous, time) in previous.zip(&times) {
+                draw_frame(&root, grid, fills, previous, *time);
+                root.present().expect("Failed to write frame.");
+            }
+            Ok(vec![output.to_path_buf()])
+        },
+        ReplayFormat::Frames => {
+            create_dir_all(output)?;
+            let mut paths = Vec::new();
+            for (i, (previous, time)) in previous.zip(&times).enumerate() {
+                let path = output.join(format!("frame-{:04}.png", i));
+                {
+                    let root = BitMapBackend::new(&path, size).into_drawing_area();
+                    draw_frame(&root, grid, fills, previous, *time);
+                    root.present().expect("Failed to write frame.");
+                }
+                paths.push(path);
+            }
+            Ok(paths)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fills(timestamps: &[u32]) -> Vec<CellFill> {
+        timestamps.iter().enumerate().map(|(cell, timestamp)| CellFill { cell, guess: "A".to_string(), timestamp: *timestamp }).collect()
+    }
+
+    #[test]
+    fn frames_start_empty_and_follow_each_fill_time() {
+        assert_eq!(frame_times(&fills(&[5, 0, 5, 12])), [0, 5, 12]);
+        assert_eq!(frame_times(&fills(&[7, 3])), [0, 3, 7]);
+        assert_eq!(frame_times(&[]), [0]);
+    }
+
+    #[test]
+    fn frames_are_bucketed_past_the_limit() {
+        // 601 fill times in steps of three seconds, ending on the last fill.
+        let times = frame_times(&fills(&(1..=601).collect::<Vec<_>>()));
+        assert_eq!(times.len(), 202);
+        assert_eq!(times[..3], [0, 3, 6]);
+        assert_eq!(times[times.len() - 2..], [600, 601]);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::util::format_duration;
+
+use thiserror::Error;
+
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Error, Debug)]
+pub enum ReportError {
+    #[error("Unknown report format: {0}")]
+    UnknownFormat(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Csv(#[from] csv::Error)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReportFormat {
+    /// Aligned columns for the terminal.
+    Table,
+    Markdown,
+    /// Raw numbers, with times in seconds, for spreadsheets.
+    Csv
+}
+
+impl FromStr for ReportFormat {
+    type Err = ReportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(ReportFormat::Table),
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "csv" => Ok(ReportFormat::Csv),
+            _ => Err(ReportError::UnknownFormat(s.to_string()))
+        }
+    }
+}
+
+/// A value in a report, formatted to suit the output.
+#[derive(Debug, Clone)]
+pub enum Cell {
+    Text(String),
+    Count(u32),
+    /// A fraction, shown as a percentage.
+    Rate(f64),
+    /// Seconds, shown as m:ss.
+    Duration(f64),
+    /// A ratio to some baseline, shown as a percentage above or below it.
+    Relative(f64),
+    Empty
+}
+
+impl Cell {
+    fn format(&self, format: ReportFormat) -> String {
+        match (self, format) {
+            (Cell::Text(text), _) => text.clone(),
+            (Cell::Count(count), _) => count.to_string(),
+            (Cell::Rate(rate), ReportFormat::Csv) => format!("{:.4}", rate),
+            (Cell::Rate(rate), _) => format!("{:.0}%", rate * 100.0),
+            (Cell::Duration(seconds), ReportFormat::Csv) => format!("{:.0}", seconds),
+            (Cell::Duration(seconds), _) => format_duration(*seconds),
+            (Cell::Relative(ratio), ReportFormat::Csv) => format!("{:.4}", ratio),
+            (Cell::Relative(ratio), _) => format_relative(*ratio),
+            (Cell::Empty, _) => String::new()
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(self, Cell::Text(_))
+    }
+}
+
+impl From<Option<f64>> for Cell {
+    fn from(seconds: Option<f64>) -> Self {
+        seconds.map(Cell::Duration).unwrap_or(Cell::Empty)
+    }
+}
+
+/// Formats a ratio to some baseline as e.g. "+12%", without a "-0%" for ratios
+/// just under 1.
+pub fn format_relative(ratio: f64) -> String {
+    format!("{:+}%", ((ratio - 1.0) * 100.0).round() + 0.0)
+}
+
+/// Writes `rows` under `headers`. Text columns are left-aligned and the rest
+/// right-aligned.
+pub fn write_report<W: Write>(headers: &[&str], rows: &[Vec<Cell>], format: ReportFormat, mut writer: W) -> Result<(), ReportError> {
+    let cells = rows.iter().map(|row| row.iter().map(|cell| cell.format(format)).collect::<Vec<_>>()).collect::<Vec<_>>();
+    let left = (0..headers.len()).map(|i| rows.first().is_some_and(|row| row[i].is_text())).collect::<Vec<_>>();
+    match format {
+        ReportFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            csv_writer.write_record(headers)?;
+            for row in &cells {
+                csv_writer.write_record(row)?;
+            }
+            csv_writer.flush()?;
+        },
+        ReportFormat::Markdown => {
+            writeln!(writer, "| {} |", headers.join(" | "))?;
+            let rule = left.iter().map(|left| if *left { ":---" } else { "---:" }).collect::<Vec<_>>();
+            writeln!(writer, "| {} |", rule.join(" | "))?;
+            for row in &cells {
+                writeln!(writer, "| {} |", row.join(" | "))?;
+            }
+        },
+        ReportFormat::Table => {
+            let widths = (0..headers.len())
+                .map(|i| cells.iter().map(|row| row[i].len()).chain(std::iter::once(headers[i].len())).max().unwrap_or(0))
+                .collect::<Vec<_>>();
+            let line = |values: Vec<&str>| values.iter().enumerate()
+                .map(|(i, value)| if left[i] { format!("{:<1$}", value, widths[i]) } else { format!("{:>1$}", value, widths[i]) })
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(writer, "{}", line(headers.to_vec()).trim_end())?;
+            for row in &cells {
+                writeln!(writer, "{}", line(row.iter().map(|c| c.as_str()).collect()).trim_end())?;
+            }
+        }
+    }
+    Ok(())
+}
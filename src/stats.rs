@@ -1,95 +1,1723 @@
-use crate::tracker::{SolveState, XwordSummary};
+use crate::puzzle::{AnswerUse, CellFill, Direction, Puzzle, PuzzleGrid};
+use crate::tracker::{ParseVariantError, PuzzleType, SolveState, SolveTimeMap, TagMap, TimestampMap, XwordSummary};
+use crate::util::{format_duration, parse_weekday_duration};
 use chrono::prelude::*;
+use chrono_tz::America::New_York;
+use chrono_tz::Tz;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use structopt::StructOpt;
 
-pub fn get_daily_moving_percentage(xwords: &Vec<XwordSummary>, window: u32) -> HashMap<Weekday, Vec<(Date<Utc>, f64)>> {
-    let mut map: HashMap<Weekday, Vec<&XwordSummary>> = HashMap::new();
+#[derive(Debug, Default)]
+pub struct TagSummary {
+    pub count: u32,
+    pub solved: u32,
+    pub gold: u32,
+    pub total_time: u64
+}
+
+impl TagSummary {
+    pub fn mean_time(&self) -> Option<f64> {
+        if self.gold == 0 {
+            None
+        } else {
+            Some(self.total_time as f64 / self.gold as f64)
+        }
+    }
+}
+
+pub fn get_tag_summaries(xwords: &[XwordSummary], tags: &TagMap) -> BTreeMap<String, TagSummary> {
+    let mut summaries: BTreeMap<String, TagSummary> = BTreeMap::new();
     for xword in xwords {
-        map.entry(xword.print_date.weekday()).or_default().push(xword);
+        for tag in tags.get(&(xword.print_date, xword.puzzle_type)).into_iter().flatten() {
+            let summary = summaries.entry(tag.clone()).or_default();
+            summary.count += 1;
+            match xword.solve_state {
+                SolveState::Unsolved => (),
+                SolveState::Solved => summary.solved += 1,
+                SolveState::Gold { time } => {
+                    summary.solved += 1;
+                    summary.gold += 1;
+                    summary.total_time += time as u64;
+                }
+            }
+        }
+    }
+    summaries
+}
+
+#[derive(Debug, Default)]
+pub struct ConstructorSummary {
+    pub count: u32,
+    pub gold: u32,
+    // Each gold time divided by the median gold time for its weekday.
+    pub relative_times: Vec<f64>,
+    pub best_time: Option<(NaiveDate, u32)>
+}
+
+impl ConstructorSummary {
+    pub fn gold_rate(&self) -> f64 {
+        self.gold as f64 / self.count as f64
     }
-    
-    let mut day_map = HashMap::new();
-    for (day, xwords) in map.into_iter() {
-        day_map.insert(day, times_to_moving_percentage(xwords, window));
+
+    pub fn mean_relative(&self) -> Option<f64> {
+        if self.relative_times.is_empty() {
+            None
+        } else {
+            Some(self.relative_times.iter().sum::<f64>() / self.relative_times.len() as f64)
+        }
     }
 
-    day_map
+    pub fn median_relative(&self) -> Option<f64> {
+        median(&self.relative_times)
+    }
 }
 
-fn times_to_moving_percentage(xwords: Vec<&XwordSummary>, window: u32) -> Vec<(Date<Utc>, f64)> {
-    let mut count = 0;
-    let mut result = Vec::new();
+/// How to order constructors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstructorSort {
+    /// Median relative time, slowest first.
+    Relative,
+    /// Most puzzles first.
+    Count,
+    Name
+}
+
+impl FromStr for ConstructorSort {
+    type Err = ParseVariantError;
 
-    for xword in &xwords[..window as usize] {
-        match xword.solve_state {
-            SolveState::Gold { .. } => count += 1,
-            _ => ()
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "relative" => Ok(ConstructorSort::Relative),
+            "count" => Ok(ConstructorSort::Count),
+            "name" => Ok(ConstructorSort::Name),
+            _ => Err(ParseVariantError::new("constructor sort", s))
         }
     }
-    result.push((xwords[(window - 1) as usize].print_date, count as f64 / window as f64));
+}
 
+/// Summarises solves by constructor, comparing each gold time against the
+/// median for its weekday so that a slow Saturday constructor isn't penalised
+/// just for making Saturdays. Co-constructed puzzles count for each constructor.
+pub fn get_constructor_summaries(xwords: &[XwordSummary], puzzles: &[Puzzle]) -> BTreeMap<String, ConstructorSummary> {
+    let baselines = get_weekday_medians(xwords);
+    let puzzles: HashMap<_, _> = puzzles.iter().map(|p| ((p.print_date, p.puzzle_type), p)).collect();
+    let mut summaries: BTreeMap<String, ConstructorSummary> = BTreeMap::new();
 
-    let mut last = 0;
-    for xword in &xwords[window as usize..] {
-        match xword.solve_state {
-            SolveState::Gold { .. } => count += 1,
-            _ => ()
+    for xword in xwords {
+        let puzzle = match puzzles.get(&(xword.print_date, xword.puzzle_type)) {
+            Some(puzzle) => puzzle,
+            None => continue
+        };
+        for constructor in &puzzle.constructors {
+            let summary = summaries.entry(constructor.clone()).or_default();
+            summary.count += 1;
+            if let SolveState::Gold { time } = xword.solve_state {
+                summary.gold += 1;
+                if let Some(baseline) = baselines.get(&xword.print_date.weekday()) {
+                    summary.relative_times.push(time as f64 / baseline);
+                }
+                if summary.best_time.is_none_or(|(_, best)| time < best) {
+                    summary.best_time = Some((xword.print_date, time));
+                }
+            }
         }
-        match xwords[last].solve_state {
-            SolveState::Gold { .. } => count -= 1,
-            _ => ()
+    }
+    summaries
+}
+
+#[derive(Debug)]
+pub struct AnswerFrequency {
+    pub answer: String,
+    pub count: u32,
+    pub first_seen: NaiveDate,
+    pub last_seen: NaiveDate,
+    pub last_clue: String
+}
+
+/// Counts how often each answer appears, most frequent first. `uses` must be
+/// in date order so the latest clue wins.
+pub fn get_answer_frequencies(uses: &[AnswerUse]) -> Vec<AnswerFrequency> {
+    let mut frequencies: HashMap<&str, AnswerFrequency> = HashMap::new();
+    for answer_use in uses {
+        let frequency = frequencies.entry(&answer_use.answer).or_insert_with(|| AnswerFrequency {
+            answer: answer_use.answer.clone(),
+            count: 0,
+            first_seen: answer_use.print_date,
+            last_seen: answer_use.print_date,
+            last_clue: String::new()
+        });
+        frequency.count += 1;
+        frequency.last_seen = answer_use.print_date;
+        frequency.last_clue = answer_use.clue.clone();
+    }
+    let mut frequencies = frequencies.into_values().collect::<Vec<_>>();
+    frequencies.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.answer.cmp(&b.answer)));
+    frequencies
+}
+
+pub fn get_answer_frequencies_by_year(uses: &[AnswerUse]) -> BTreeMap<i32, Vec<AnswerFrequency>> {
+    let mut years: BTreeMap<i32, Vec<AnswerUse>> = BTreeMap::new();
+    for answer_use in uses {
+        years.entry(answer_use.print_date.year()).or_default().push(answer_use.clone());
+    }
+    years.into_iter().map(|(year, uses)| (year, get_answer_frequencies(&uses))).collect()
+}
+
+#[derive(Debug)]
+pub struct CommonFillPoint {
+    pub print_date: NaiveDate,
+    /// Fraction of the puzzle's answers that appear in at least `min_count`
+    /// other puzzles.
+    pub common_share: f64,
+    /// Gold time divided by the median gold time for the weekday.
+    pub relative_time: f64
+}
+
+/// Pairs each gold solve with how much of its fill is common, so the two can
+/// be correlated. An answer's own puzzle doesn't count towards making it common.
+pub fn get_common_fill_points(xwords: &[XwordSummary], uses: &[AnswerUse], min_count: u32) -> Vec<CommonFillPoint> {
+    let mut counts: HashMap<&str, u32> = HashMap::new();
+    let mut puzzles: HashMap<_, Vec<&str>> = HashMap::new();
+    for answer_use in uses {
+        *counts.entry(&answer_use.answer).or_default() += 1;
+        puzzles.entry((answer_use.print_date, answer_use.puzzle_type)).or_default().push(&answer_use.answer);
+    }
+    let baselines = get_weekday_medians(xwords);
+
+    let mut points = Vec::new();
+    for xword in xwords {
+        let time = match xword.solve_state {
+            SolveState::Gold { time } => time,
+            _ => continue
+        };
+        let (answers, baseline) = match (puzzles.get(&(xword.print_date, xword.puzzle_type)), baselines.get(&xword.print_date.weekday())) {
+            (Some(answers), Some(baseline)) => (answers, baseline),
+            _ => continue
+        };
+        let mut in_puzzle: HashMap<&str, u32> = HashMap::new();
+        for answer in answers {
+            *in_puzzle.entry(answer).or_default() += 1;
         }
-        result.push((xword.print_date, count as f64 / window as f64));
-        last += 1
+        let common = answers.iter().filter(|a| counts[*a] - in_puzzle[*a] >= min_count).count();
+        points.push(CommonFillPoint {
+            print_date: xword.print_date,
+            common_share: common as f64 / answers.len() as f64,
+            relative_time: time as f64 / baseline
+        });
     }
+    points
+}
 
-    result
+/// A gold solve that beat every earlier solve of its weekday and puzzle type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersonalBest {
+    pub print_date: NaiveDate,
+    pub puzzle_type: PuzzleType,
+    pub time: u32,
+    /// The best it beat, or None for the first gold solve of its weekday.
+    pub previous: Option<u32>,
+    /// When it was solved, if known.
+    pub solved_at: Option<DateTime<Utc>>
+}
+
+impl PersonalBest {
+    /// When the best was set: when it was solved, or failing that when the
+    /// puzzle came out.
+    pub fn set_at(&self) -> DateTime<Utc> {
+        self.solved_at.unwrap_or_else(|| release_time(self.print_date))
+    }
+}
+
+/// Every successive personal best for each weekday and puzzle type, in the
+/// order they were set, so that an archive puzzle solved today beats today's
+/// best rather than rewriting history. Solves with no known solve time are
+/// taken to have been solved when they came out.
+pub fn get_personal_bests(xwords: &[XwordSummary], solve_times: &SolveTimeMap) -> Vec<PersonalBest> {
+    let mut sorted = xwords.iter()
+        .map(|x| (x, solve_times.get(&(x.print_date, x.puzzle_type)).copied()))
+        .collect::<Vec<_>>();
+    sorted.sort_by_key(|(x, solved_at)| (solved_at.unwrap_or_else(|| release_time(x.print_date)), x.print_date));
+    let mut bests: HashMap<(PuzzleType, Weekday), u32> = HashMap::new();
+    let mut progression = Vec::new();
+    for (xword, solved_at) in sorted {
+        let time = match xword.solve_state {
+            SolveState::Gold { time } => time,
+            _ => continue
+        };
+        let previous = bests.get(&(xword.puzzle_type, xword.print_date.weekday())).copied();
+        if previous.is_none_or(|best| time < best) {
+            bests.insert((xword.puzzle_type, xword.print_date.weekday()), time);
+            progression.push(PersonalBest { print_date: xword.print_date, puzzle_type: xword.puzzle_type, time, previous, solved_at });
+        }
+    }
+    progression
+}
+
+/// When the puzzle for `date` is released: 10pm ET the evening before, or 6pm
+/// for Saturday and Sunday puzzles.
+pub fn release_time(date: NaiveDate) -> DateTime<Utc> {
+    let hour = match date.weekday() {
+        Weekday::Sat | Weekday::Sun => 18,
+        _ => 22
+    };
+    let evening = (date - chrono::Duration::days(1)).and_hms_opt(hour, 0, 0).expect("Invalid release hour");
+    New_York.from_local_datetime(&evening).unwrap().with_timezone(&Utc)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Streak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub length: u32
+}
+
+#[derive(Debug, Default)]
+pub struct StreakSummary {
+    pub current: Option<Streak>,
+    pub longest: Option<Streak>,
+    /// The streak as of each day, from the first solve on.
+    pub history: Vec<(NaiveDate, u32)>,
+    /// Solves whose first-solved time we don't know, e.g. manual or imported
+    /// ones, or those synced before it was tracked.
+    pub unknown: u32
+}
+
+/// Computes daily streaks the way the NYTimes does: a puzzle counts only if
+/// it was solved before the next day's puzzle was released. With `gold_only`
+/// it must also have been solved without help. Solves with no known solve
+/// time count only if `lenient` is set. Today's puzzle doesn't break the
+/// streak until it closes.
+pub fn get_streaks(xwords: &[XwordSummary], solve_times: &SolveTimeMap, gold_only: bool, lenient: bool, now: DateTime<Utc>) -> StreakSummary {
+    let solves: HashMap<_, _> = xwords.iter()
+        .filter(|x| x.puzzle_type == PuzzleType::Daily)
+        .filter(|x| match x.solve_state {
+            SolveState::Unsolved => false,
+            SolveState::Solved => !gold_only,
+            SolveState::Gold { .. } => true
+        })
+        .map(|x| (x.print_date, x))
+        .collect();
+    let mut summary = StreakSummary::default();
+    let mut date = match solves.keys().min() {
+        Some(first) => *first,
+        None => return summary
+    };
+
+    let mut current: Option<Streak> = None;
+    while release_time(date) <= now {
+        let closes = release_time(date + chrono::Duration::days(1));
+        let counts = solves.contains_key(&date) && match solve_times.get(&(date, PuzzleType::Daily)) {
+            Some(solved_at) => *solved_at < closes,
+            None => {
+                summary.unknown += 1;
+                lenient
+            }
+        };
+        if !counts && now < closes {
+            break;
+        }
+        current = if counts {
+            let streak = match current {
+                Some(streak) => Streak { end: date, length: streak.length + 1, ..streak },
+                None => Streak { start: date, end: date, length: 1 }
+            };
+            if summary.longest.is_none_or(|longest| streak.length > longest.length) {
+                summary.longest = Some(streak);
+            }
+            Some(streak)
+        } else {
+            None
+        };
+        summary.history.push((date, current.map(|s| s.length).unwrap_or(0)));
+        date += chrono::Duration::days(1);
+    }
+    summary.current = current;
+    summary
+}
+
+static REGIONS: [&str; 9] = ["NW", "N", "NE", "W", "center", "E", "SW", "S", "SE"];
+
+/// How long one entry of a solved grid held us up.
+#[derive(Debug)]
+pub struct EntryTime {
+    pub direction: Direction,
+    pub label: String,
+    pub text: String,
+    pub answer: String,
+    pub length: usize,
+    /// Which ninth of the grid the entry's middle falls in, e.g. "SE".
+    pub region: &'static str,
+    /// Seconds into the solve at which the entry's last cell was filled.
+    pub completed: u32,
+    /// Seconds from the entry's first cell being filled to its last.
+    pub span: u32,
+    /// Seconds of the solve spent before filling the entry's cells: each pause
+    /// between fills is charged to the cells filled at the end of it.
+    pub stall: f64,
+    /// Stall per cell, relative to the puzzle's average time per cell. 1.0 is
+    /// an ordinary entry; comparable across puzzles of any difficulty.
+    pub relative_stall: f64
+}
+
+pub fn length_group(length: usize) -> &'static str {
+    match length {
+        0..=4 => "short",
+        5..=7 => "medium",
+        _ => "long"
+    }
+}
+
+/// Times every fully filled entry of a grid from when its cells were filled.
+pub fn get_entry_times(grid: &PuzzleGrid, fills: &[CellFill]) -> Vec<EntryTime> {
+    let mut sorted = fills.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|f| f.timestamp);
+    let mut stalls: HashMap<usize, f64> = HashMap::new();
+    let mut previous = 0;
+    for group in sorted.chunk_by(|a, b| a.timestamp == b.timestamp) {
+        let pause = (group[0].timestamp - previous) as f64 / group.len() as f64;
+        for fill in group {
+            stalls.insert(fill.cell, pause);
+        }
+        previous = group[0].timestamp;
+    }
+    let times: HashMap<usize, u32> = fills.iter().map(|f| (f.cell, f.timestamp)).collect();
+    let per_cell = previous as f64 / stalls.len().max(1) as f64;
+
+    let mut entries = Vec::new();
+    for clue in &grid.clues {
+        let cell_times = clue.cells.iter().filter_map(|c| times.get(c).copied()).collect::<Vec<_>>();
+        if clue.cells.is_empty() || cell_times.len() < clue.cells.len() {
+            continue;
+        }
+        let (rows, cols) = clue.cells.iter().fold((0, 0), |(r, c), i| (r + i / grid.width as usize, c + i % grid.width as usize));
+        let row = rows * 3 / clue.cells.len() / grid.height as usize;
+        let col = cols * 3 / clue.cells.len() / grid.width as usize;
+        let stall = clue.cells.iter().map(|c| stalls[c]).sum::<f64>();
+        let completed = *cell_times.iter().max().unwrap();
+        entries.push(EntryTime {
+            direction: clue.direction,
+            label: clue.label.clone(),
+            text: clue.text.clone(),
+            answer: grid.answer(clue),
+            length: clue.cells.len(),
+            region: REGIONS[row.min(2) * 3 + col.min(2)],
+            completed,
+            span: completed - cell_times.iter().min().unwrap(),
+            stall,
+            relative_stall: if per_cell > 0.0 { stall / clue.cells.len() as f64 / per_cell } else { 0.0 }
+        });
+    }
+    entries
+}
+
+#[derive(Debug, Default)]
+pub struct StallSummary {
+    pub count: u32,
+    pub total_relative: f64
+}
+
+impl StallSummary {
+    pub fn mean_relative(&self) -> f64 {
+        self.total_relative / self.count as f64
+    }
+}
+
+/// Groups entries from any number of puzzles by `key`, e.g. direction,
+/// length or region, to find the kinds of entry that slow us down.
+pub fn get_stall_summaries<K: Ord, F: Fn(&EntryTime) -> K>(entries: &[EntryTime], key: F) -> BTreeMap<K, StallSummary> {
+    let mut summaries: BTreeMap<K, StallSummary> = BTreeMap::new();
+    for entry in entries {
+        let summary = summaries.entry(key(entry)).or_default();
+        summary.count += 1;
+        summary.total_relative += entry.relative_stall;
+    }
+    summaries
+}
+
+/// Pearson correlation coefficient, or None if either series is constant.
+pub fn pearson(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let cov = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>();
+    let var_x = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+    let var_y = points.iter().map(|(_, y)| (y - mean_y).powi(2)).sum::<f64>();
+    if var_x == 0.0 || var_y == 0.0 {
+        None
+    } else {
+        Some(cov / (var_x * var_y).sqrt())
+    }
+}
+
+/// A span of the calendar to aggregate solves over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Month,
+    Quarter,
+    Year
+}
+
+impl Period {
+    /// Labels that sort in calendar order, e.g. "2021-03", "2021-Q1" or "2021".
+    pub fn label(&self, date: NaiveDate) -> String {
+        match self {
+            Period::Month => date.format("%Y-%m").to_string(),
+            Period::Quarter => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
+            Period::Year => date.year().to_string()
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = ParseVariantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "month" | "monthly" => Ok(Period::Month),
+            "quarter" | "quarterly" => Ok(Period::Quarter),
+            "year" | "yearly" => Ok(Period::Year),
+            _ => Err(ParseVariantError::new("period", s))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PeriodSummary {
+    pub count: u32,
+    pub solved: u32,
+    pub times: Vec<f64>
 }
 
-pub fn get_daily_moving_averages(xwords: &Vec<XwordSummary>, window: u32) -> HashMap<Weekday, Vec<(Date<Utc>, f64)>> {
-    let mut map: HashMap<Weekday, Vec<&XwordSummary>> = HashMap::new();
+impl PeriodSummary {
+    fn add(&mut self, solve_state: SolveState) {
+        self.count += 1;
+        match solve_state {
+            SolveState::Unsolved => (),
+            SolveState::Solved => self.solved += 1,
+            SolveState::Gold { time } => {
+                self.solved += 1;
+                self.times.push(time as f64);
+            }
+        }
+    }
+
+    pub fn gold(&self) -> u32 {
+        self.times.len() as u32
+    }
+
+    pub fn gold_rate(&self) -> f64 {
+        self.gold() as f64 / self.count as f64
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        Statistic::Mean.apply(&self.times)
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        median(&self.times)
+    }
+
+    pub fn best(&self) -> Option<f64> {
+        self.times.iter().copied().reduce(f64::min)
+    }
+}
+
+/// Solves in one period, for each weekday (from Monday) and overall.
+#[derive(Debug, Default)]
+pub struct PeriodReport {
+    pub by_weekday: BTreeMap<u32, PeriodSummary>,
+    pub overall: PeriodSummary
+}
+
+pub fn get_period_reports(xwords: &[XwordSummary], period: Period) -> BTreeMap<String, PeriodReport> {
+    let mut reports: BTreeMap<String, PeriodReport> = BTreeMap::new();
     for xword in xwords {
-        map.entry(xword.print_date.weekday()).or_default().push(xword);
+        let report = reports.entry(period.label(xword.print_date)).or_default();
+        report.by_weekday.entry(xword.print_date.weekday().num_days_from_monday()).or_default().add(xword.solve_state);
+        report.overall.add(xword.solve_state);
     }
-    
-    let mut day_map = HashMap::new();
-    for (day, xwords) in map.into_iter() {
-        day_map.insert(day, times_to_moving_average(xwords, window));
+    reports
+}
+
+/// How long after its release a puzzle was solved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Recency {
+    /// Before the next day's puzzle came out.
+    SameDay,
+    WithinWeek,
+    Archive,
+    /// Manual and imported solves, and solves synced before timestamps were
+    /// tracked.
+    Unknown
+}
+
+impl Recency {
+    /// Goes by when the puzzle was first opened, if known, since a solve
+    /// started on the day counts as a day-of attempt however long it took.
+    pub fn classify(date: NaiveDate, opened_at: Option<DateTime<Utc>>, solved_at: Option<DateTime<Utc>>) -> Recency {
+        match opened_at.or(solved_at) {
+            Some(at) if at < release_time(date + chrono::Duration::days(1)) => Recency::SameDay,
+            Some(at) if at < release_time(date + chrono::Duration::days(7)) => Recency::WithinWeek,
+            Some(_) => Recency::Archive,
+            None => Recency::Unknown
+        }
     }
 
-    day_map
+    pub fn label(&self) -> &'static str {
+        match self {
+            Recency::SameDay => "same day",
+            Recency::WithinWeek => "within a week",
+            Recency::Archive => "archive",
+            Recency::Unknown => "unknown"
+        }
+    }
 }
 
-fn times_to_moving_average(xwords: Vec<&XwordSummary>, window: u32) -> Vec<(Date<Utc>, f64)> {
-    let mut total = 0;
-    let mut count = 0;
-    let mut last = 0;
-    let mut result = Vec::new();
+/// Solves grouped by how soon after release they happened. Only solved puzzles
+/// have timestamps, so unsolved ones are left out.
+pub fn get_recency_reports(xwords: &[XwordSummary], open_times: &TimestampMap, solve_times: &SolveTimeMap) -> BTreeMap<Recency, PeriodReport> {
+    let mut reports: BTreeMap<Recency, PeriodReport> = BTreeMap::new();
+    for xword in xwords.iter().filter(|x| x.solve_state != SolveState::Unsolved) {
+        let key = (xword.print_date, xword.puzzle_type);
+        let recency = Recency::classify(xword.print_date, open_times.get(&key).copied(), solve_times.get(&key).copied());
+        let report = reports.entry(recency).or_default();
+        report.by_weekday.entry(xword.print_date.weekday().num_days_from_monday()).or_default().add(xword.solve_state);
+        report.overall.add(xword.solve_state);
+    }
+    reports
+}
+
+/// Solves finished in one hour of the day, or on one local weekday.
+#[derive(Debug, Default)]
+pub struct ClockSummary {
+    pub solves: PeriodSummary,
+    // Each gold time divided by the median gold time for its puzzle's weekday.
+    pub relative_times: Vec<f64>
+}
+
+impl ClockSummary {
+    fn add(&mut self, xword: &XwordSummary, baselines: &HashMap<Weekday, f64>) {
+        self.solves.add(xword.solve_state);
+        if let (SolveState::Gold { time }, Some(baseline)) = (xword.solve_state, baselines.get(&xword.print_date.weekday())) {
+            self.relative_times.push(time as f64 / baseline);
+        }
+    }
+
+    pub fn median_relative(&self) -> Option<f64> {
+        median(&self.relative_times)
+    }
+}
+
+/// When solves were finished, on the clock of the solver's timezone.
+#[derive(Debug, Default)]
+pub struct SolveClock {
+    /// Solves by local weekday (from Monday) and hour.
+    pub counts: BTreeMap<(u32, u32), u32>,
+    pub by_hour: BTreeMap<u32, ClockSummary>,
+    pub by_weekday: BTreeMap<u32, ClockSummary>
+}
+
+/// Buckets solves by when they were finished in `timezone`. Times are also
+/// compared against the median for their puzzle's weekday, since a Saturday
+/// solved at midnight isn't slow for being a Saturday. Solves without a
+/// completion time are left out.
+pub fn get_solve_clock(xwords: &[XwordSummary], solve_times: &SolveTimeMap, timezone: Tz) -> SolveClock {
+    let baselines = get_weekday_medians(xwords);
+    let mut clock = SolveClock::default();
+    for xword in xwords.iter().filter(|x| x.solve_state != SolveState::Unsolved) {
+        let solved_at = match solve_times.get(&(xword.print_date, xword.puzzle_type)) {
+            Some(solved_at) => solved_at.with_timezone(&timezone),
+            None => continue
+        };
+        let (day, hour) = (solved_at.weekday().num_days_from_monday(), solved_at.hour());
+        *clock.counts.entry((day, hour)).or_default() += 1;
+        clock.by_hour.entry(hour).or_default().add(xword, &baselines);
+        clock.by_weekday.entry(day).or_default().add(xword, &baselines);
+    }
+    clock
+}
+
+/// How far we got with a puzzle in the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Completion {
+    /// Never opened, or at least never synced.
+    Untouched,
+    Unsolved,
+    Solved,
+    Gold
+}
+
+/// The state of every daily puzzle published from `start` to `end`.
+pub fn get_archive(xwords: &[XwordSummary], start: NaiveDate, end: NaiveDate) -> BTreeMap<NaiveDate, Completion> {
+    let mut archive = BTreeMap::new();
+    let mut date = start;
+    while date <= end {
+        archive.insert(date, Completion::Untouched);
+        date += chrono::Duration::days(1);
+    }
+    for xword in xwords.iter().filter(|x| x.puzzle_type == PuzzleType::Daily) {
+        if let Some(completion) = archive.get_mut(&xword.print_date) {
+            *completion = match xword.solve_state {
+                SolveState::Unsolved => Completion::Unsolved,
+                SolveState::Solved => Completion::Solved,
+                SolveState::Gold { .. } => Completion::Gold
+            };
+        }
+    }
+    archive
+}
+
+#[derive(Debug, Default)]
+pub struct CompletionSummary {
+    pub puzzles: u32,
+    pub unsolved: u32,
+    pub solved: u32,
+    pub gold: u32
+}
+
+impl CompletionSummary {
+    fn add(&mut self, completion: Completion) {
+        self.puzzles += 1;
+        match completion {
+            Completion::Untouched => (),
+            Completion::Unsolved => self.unsolved += 1,
+            Completion::Solved => self.solved += 1,
+            Completion::Gold => {
+                self.solved += 1;
+                self.gold += 1;
+            }
+        }
+    }
+
+    pub fn untouched(&self) -> u32 {
+        self.puzzles - self.unsolved - self.solved
+    }
+
+    pub fn solved_share(&self) -> f64 {
+        self.solved as f64 / self.puzzles as f64
+    }
+}
+
+/// Archive completion for one publication year, for each weekday (from
+/// Monday) and overall.
+#[derive(Debug, Default)]
+pub struct CompletionReport {
+    pub by_weekday: BTreeMap<u32, CompletionSummary>,
+    pub overall: CompletionSummary
+}
+
+pub fn get_completion_reports(archive: &BTreeMap<NaiveDate, Completion>) -> BTreeMap<i32, CompletionReport> {
+    let mut reports: BTreeMap<i32, CompletionReport> = BTreeMap::new();
+    for (date, completion) in archive {
+        let report = reports.entry(date.year()).or_default();
+        report.by_weekday.entry(date.weekday().num_days_from_monday()).or_default().add(*completion);
+        report.overall.add(*completion);
+    }
+    reports
+}
+
+/// Median gold time for each weekday.
+pub fn get_weekday_medians(xwords: &[XwordSummary]) -> HashMap<Weekday, f64> {
+    let mut times: HashMap<Weekday, Vec<f64>> = HashMap::new();
+    for xword in xwords {
+        if let SolveState::Gold { time } = xword.solve_state {
+            times.entry(xword.print_date.weekday()).or_default().push(time as f64);
+        }
+    }
+    times.into_iter().filter_map(|(day, times)| median(&times).map(|m| (day, m))).collect()
+}
+
+pub fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        Some((sorted[mid - 1] + sorted[mid]) / 2.0)
+    } else {
+        Some(sorted[mid])
+    }
+}
+
+/// The `p`th percentile, interpolating between the closest ranks.
+pub fn percentile(values: &[f64], p: u32) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = p.min(100) as f64 / 100.0 * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+/// Mean after dropping `percent`% of the values from each end.
+pub fn trimmed_mean(values: &[f64], percent: u32) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let cut = sorted.len() * percent.min(49) as usize / 100;
+    let kept = &sorted[cut..sorted.len() - cut];
+    if kept.is_empty() {
+        None
+    } else {
+        Some(kept.iter().sum::<f64>() / kept.len() as f64)
+    }
+}
+
+/// Mean after clamping `percent`% of the values at each end to the nearest
+/// value that's kept.
+pub fn winsorized_mean(values: &[f64], percent: u32) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let cut = sorted.len() * percent.min(49) as usize / 100;
+    let (low, high) = (sorted[cut], sorted[sorted.len() - 1 - cut]);
+    Some(sorted.iter().map(|v| v.clamp(low, high)).sum::<f64>() / sorted.len() as f64)
+}
+
+// How to spot gold times that are wall-clock artifacts rather than solving,
+// judged against other solves of the same weekday and puzzle type. Only slow
+// times are flagged.
+#[derive(StructOpt, Debug, Default)]
+pub struct OutlierRules {
+    /// Flag daily puzzle times over this on a weekday, e.g. sat=1:30:00, or
+    /// prefix a puzzle type for others, e.g. mini:sat=2:00; may be repeated
+    #[structopt(long = "cap", parse(try_from_str = parse_cap), number_of_values = 1)]
+    pub caps: Vec<((PuzzleType, Weekday), u32)>,
+
+    /// Flag times more than this many scaled median absolute deviations above
+    /// the median
+    #[structopt(long)]
+    pub mad: Option<f64>,
+
+    /// Flag times more than this many interquartile ranges above the upper
+    /// quartile
+    #[structopt(long)]
+    pub iqr: Option<f64>
+}
+
+impl OutlierRules {
+    pub fn is_empty(&self) -> bool {
+        self.caps.is_empty() && self.mad.is_none() && self.iqr.is_none()
+    }
+}
+
+/// Reads a cap as `weekday=time`, for daily puzzles, or `type:weekday=time`.
+fn parse_cap(s: &str) -> Result<((PuzzleType, Weekday), u32), String> {
+    let (puzzle_type, cap) = match s.split_once('=').and_then(|(key, _)| key.split_once(':')) {
+        Some((puzzle_type, _)) => (puzzle_type.trim().parse::<PuzzleType>().map_err(|e| e.to_string())?, &s[puzzle_type.len() + 1..]),
+        None => (PuzzleType::Daily, s)
+    };
+    let (weekday, time) = parse_weekday_duration(cap)?;
+    Ok(((puzzle_type, weekday), time))
+}
+
+// Scales the MAD to match the standard deviation of a normal distribution.
+static MAD_SCALE: f64 = 1.4826;
+
+/// A solve's date and puzzle type, with the first rule it broke.
+pub type DetectedOutlier = ((NaiveDate, PuzzleType), String);
+
+/// The gold solves that break any of `rules`.
+pub fn get_outliers(xwords: &[XwordSummary], rules: &OutlierRules) -> Vec<DetectedOutlier> {
+    let mut groups: HashMap<_, Vec<(NaiveDate, f64)>> = HashMap::new();
+    for xword in xwords {
+        if let SolveState::Gold { time } = xword.solve_state {
+            groups.entry((xword.puzzle_type, xword.print_date.weekday())).or_default().push((xword.print_date, time as f64));
+        }
+    }
+
+    let mut outliers = Vec::new();
+    for ((puzzle_type, weekday), points) in groups {
+        let values = points.iter().map(|(_, time)| *time).collect::<Vec<_>>();
+        let cap = rules.caps.iter().filter(|(key, _)| *key == (puzzle_type, weekday)).map(|(_, cap)| *cap as f64).fold(f64::INFINITY, f64::min);
+        let mad_limit = rules.mad.and_then(|k| {
+            let middle = median(&values)?;
+            let mad = median(&values.iter().map(|v| (v - middle).abs()).collect::<Vec<_>>())? * MAD_SCALE;
+            Some(middle + k * mad).filter(|_| mad > 0.0)
+        });
+        let iqr_limit = rules.iqr.and_then(|k| {
+            let (q1, q3) = (percentile(&values, 25)?, percentile(&values, 75)?);
+            Some(q3 + k * (q3 - q1)).filter(|_| q3 > q1)
+        });
+
+        for (date, time) in points {
+            let reason = if time > cap {
+                format!("over {} cap", format_duration(cap))
+            } else if let Some(limit) = mad_limit.filter(|limit| time > *limit) {
+                format!("over {} (median + {} MADs)", format_duration(limit), rules.mad.unwrap_or_default())
+            } else if let Some(limit) = iqr_limit.filter(|limit| time > *limit) {
+                format!("over {} (Q3 + {} IQRs)", format_duration(limit), rules.iqr.unwrap_or_default())
+            } else {
+                continue;
+            };
+            outliers.push(((date, puzzle_type), reason));
+        }
+    }
+    outliers.sort_by_key(|(key, _)| *key);
+    outliers
+}
+
+/// The five numbers drawn in a box plot, with whiskers reaching the furthest
+/// values within 1.5 IQR of the box and anything beyond that an outlier.
+#[derive(Debug, Clone)]
+pub struct BoxSummary {
+    pub low: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub high: f64,
+    pub outliers: Vec<f64>
+}
+
+pub fn box_summary(values: &[f64]) -> Option<BoxSummary> {
+    let (q1, median, q3) = (percentile(values, 25)?, percentile(values, 50)?, percentile(values, 75)?);
+    let (low_fence, high_fence) = (q1 - 1.5 * (q3 - q1), q3 + 1.5 * (q3 - q1));
+    let inside = values.iter().copied().filter(|v| *v >= low_fence && *v <= high_fence);
+    Some(BoxSummary {
+        low: inside.clone().fold(q1, f64::min),
+        q1,
+        median,
+        q3,
+        high: inside.fold(q3, f64::max),
+        outliers: values.iter().copied().filter(|v| *v < low_fence || *v > high_fence).collect()
+    })
+}
+
+/// Silverman's rule-of-thumb bandwidth for a Gaussian kernel density
+/// estimate, or None if there's no spread to estimate from.
+pub fn silverman_bandwidth(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let sd = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+    let iqr = percentile(values, 75)? - percentile(values, 25)?;
+    let spread = if iqr > 0.0 { sd.min(iqr / 1.34) } else { sd };
+    Some(0.9 * spread * n.powf(-0.2)).filter(|h| *h > 0.0)
+}
+
+/// Gaussian kernel density of `values` at `x`; integrates to 1.
+pub fn kernel_density(values: &[f64], bandwidth: f64, x: f64) -> f64 {
+    let norm = (2.0 * std::f64::consts::PI).sqrt() * bandwidth * values.len() as f64;
+    values.iter().map(|v| (-0.5 * ((x - v) / bandwidth).powi(2)).exp()).sum::<f64>() / norm
+}
+
+/// A straight line through log solve times against years elapsed, so the
+/// slope is a steady proportional improvement rather than a fixed number of
+/// seconds, which would eventually predict negative times.
+#[derive(Debug, Clone, Copy)]
+pub struct Trend {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub count: usize,
+    pub intercept: f64,
+    pub slope: f64,
+    pub slope_error: f64
+}
+
+// Beyond this a projected crossing says more about the fit than the solver.
+static MAX_PROJECTION_YEARS: f64 = 50.0;
 
-    for xword in xwords.iter() {
-        if let SolveState::Gold { time: curr_time } = xword.solve_state {
-            if count < window {
-                count += 1;
+impl Trend {
+    fn years(&self, date: NaiveDate) -> f64 {
+        (date - self.start).num_days() as f64 / 365.25
+    }
+
+    /// The fitted solve time on `date`, in seconds.
+    pub fn at(&self, date: NaiveDate) -> f64 {
+        (self.intercept + self.slope * self.years(date)).exp()
+    }
+
+    /// The fitted solve time as of the last solve.
+    pub fn latest(&self) -> f64 {
+        self.at(self.end)
+    }
+
+    /// The fitted change in solve time over a year, e.g. -0.1 for 10% faster.
+    pub fn yearly_change(&self) -> f64 {
+        self.slope.exp() - 1.0
+    }
+
+    /// An approximate 95% confidence interval for `yearly_change`.
+    pub fn yearly_change_interval(&self) -> (f64, f64) {
+        ((self.slope - 1.96 * self.slope_error).exp() - 1.0, (self.slope + 1.96 * self.slope_error).exp() - 1.0)
+    }
+
+    /// When the fitted time passes `target` seconds, which may be in the past,
+    /// or None if the trend is flat or the date is implausibly far off. The fit
+    /// is of log times, so it tracks their geometric mean, which sits below the
+    /// arithmetic mean whenever times vary; a moving mean of the same solves
+    /// reaches `target` later than this says.
+    pub fn crossing(&self, target: f64) -> Option<NaiveDate> {
+        if self.slope == 0.0 || target <= 0.0 {
+            return None;
+        }
+        let years = (target.ln() - self.intercept) / self.slope;
+        if years.abs() > MAX_PROJECTION_YEARS {
+            return None;
+        }
+        Some(self.start + chrono::Duration::days((years * 365.25).round() as i64))
+    }
+}
+
+/// Least-squares fit of log times against date, or None without at least
+/// three solves spread over more than one day.
+pub fn fit_trend(points: &[(NaiveDate, f64)]) -> Option<Trend> {
+    let start = points.iter().map(|(date, _)| *date).min()?;
+    let end = points.iter().map(|(date, _)| *date).max()?;
+    let xy = points.iter()
+        .filter(|(_, time)| *time > 0.0)
+        .map(|(date, time)| ((*date - start).num_days() as f64 / 365.25, time.ln()))
+        .collect::<Vec<_>>();
+    if xy.len() < 3 {
+        return None;
+    }
+    let n = xy.len() as f64;
+    let mean_x = xy.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = xy.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx = xy.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = xy.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>() / sxx;
+    let intercept = mean_y - slope * mean_x;
+    let residuals = xy.iter().map(|(x, y)| (y - intercept - slope * x).powi(2)).sum::<f64>();
+    Some(Trend {
+        start,
+        end,
+        count: xy.len(),
+        intercept,
+        slope,
+        slope_error: (residuals / (n - 2.0) / sxx).sqrt()
+    })
+}
+
+/// A way of summarising a set of solve times. Unlike the mean, the others
+/// aren't thrown off by the odd solve left open overnight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Statistic {
+    Mean,
+    Median,
+    Percentile(u32),
+    TrimmedMean(u32),
+    WinsorizedMean(u32)
+}
+
+impl Statistic {
+    pub fn apply(&self, values: &[f64]) -> Option<f64> {
+        match self {
+            Statistic::Mean if values.is_empty() => None,
+            Statistic::Mean => Some(values.iter().sum::<f64>() / values.len() as f64),
+            Statistic::Median => median(values),
+            Statistic::Percentile(p) => percentile(values, *p),
+            Statistic::TrimmedMean(percent) => trimmed_mean(values, *percent),
+            Statistic::WinsorizedMean(percent) => winsorized_mean(values, *percent)
+        }
+    }
+
+    /// A plural name for chart titles, e.g. "Medians".
+    pub fn title(&self) -> String {
+        match self {
+            Statistic::Mean => "Averages".to_string(),
+            Statistic::Median => "Medians".to_string(),
+            Statistic::Percentile(p) => format!("{}th Percentiles", p),
+            Statistic::TrimmedMean(percent) => format!("{}% Trimmed Means", percent),
+            Statistic::WinsorizedMean(percent) => format!("{}% Winsorized Means", percent)
+        }
+    }
+}
+
+impl fmt::Display for Statistic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statistic::Mean => f.pad("mean"),
+            Statistic::Median => f.pad("median"),
+            Statistic::Percentile(p) => f.pad(&format!("p{}", p)),
+            Statistic::TrimmedMean(percent) => f.pad(&format!("trimmed{}", percent)),
+            Statistic::WinsorizedMean(percent) => f.pad(&format!("winsorized{}", percent))
+        }
+    }
+}
+
+/// Parses "mean", "median", "p90", "trimmed10" or "winsorized5", the numbers
+/// being percentages.
+impl FromStr for Statistic {
+    type Err = ParseVariantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let percent = |prefix: &str| s.strip_prefix(prefix).and_then(|n| n.parse::<u32>().ok()).filter(|n| *n <= 100);
+        match s.as_str() {
+            "mean" => Ok(Statistic::Mean),
+            "median" => Ok(Statistic::Median),
+            _ => if let Some(p) = percent("p") {
+                Ok(Statistic::Percentile(p))
+            } else if let Some(percent) = percent("trimmed").filter(|n| *n < 50) {
+                Ok(Statistic::TrimmedMean(percent))
+            } else if let Some(percent) = percent("winsorized").filter(|n| *n < 50) {
+                Ok(Statistic::WinsorizedMean(percent))
             } else {
-                loop {
-                    last += 1;
-                    match xwords[last].solve_state {
-                        SolveState::Gold { time: last_time } => {
-                            total -= last_time;
-                            break
-                        },
-                        _ => continue
-                    }
+                Err(ParseVariantError::new("statistic", &s))
+            }
+        }
+    }
+}
+
+/// Gold times grouped by `key`, e.g. weekday or year, in date order.
+pub fn get_gold_times<K: Ord, F: Fn(&XwordSummary) -> K>(xwords: &[XwordSummary], key: F) -> BTreeMap<K, Vec<(NaiveDate, f64)>> {
+    let mut times: BTreeMap<K, Vec<(NaiveDate, f64)>> = BTreeMap::new();
+    for xword in xwords {
+        if let SolveState::Gold { time } = xword.solve_state {
+            times.entry(key(xword)).or_default().push((xword.print_date, time as f64));
+        }
+    }
+    for points in times.values_mut() {
+        points.sort_by_key(|(date, _)| *date);
+    }
+    times
+}
+
+/// Like `get_gold_times`, but dated by when each puzzle was solved where
+/// that's known, so an archive solve counts as of when it was done.
+pub fn get_gold_times_by_solve_date<K: Ord, F: Fn(&XwordSummary) -> K>(xwords: &[XwordSummary], solve_times: &SolveTimeMap, key: F) -> BTreeMap<K, Vec<(NaiveDate, f64)>> {
+    let mut times: BTreeMap<K, Vec<(NaiveDate, f64)>> = BTreeMap::new();
+    for xword in xwords {
+        if let SolveState::Gold { time } = xword.solve_state {
+            let date = solve_times.get(&(xword.print_date, xword.puzzle_type))
+                .map(|solved_at| solved_at.date_naive())
+                .unwrap_or(xword.print_date);
+            times.entry(key(xword)).or_default().push((date, time as f64));
+        }
+    }
+    for points in times.values_mut() {
+        points.sort_by_key(|(date, _)| *date);
+    }
+    times
+}
+
+/// How far back a rolling value looks: a number of puzzles, or a span of
+/// calendar days that doesn't stretch across gaps in solving.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Puzzles(u32),
+    Days(u32)
+}
+
+impl Window {
+    /// How many samples a window needs before it's worth reporting, unless
+    /// told otherwise: a full window of puzzles, or a handful of days' worth.
+    pub fn default_min_samples(&self) -> usize {
+        match self {
+            Window::Puzzles(count) => *count as usize,
+            Window::Days(_) => 5
+        }
+    }
+
+    /// For chart titles, e.g. "30-Puzzle".
+    pub fn title(&self) -> String {
+        match self {
+            Window::Puzzles(count) => format!("{}-Puzzle", count),
+            Window::Days(days) if days % 7 == 0 => format!("{}-Week", days / 7),
+            Window::Days(days) => format!("{}-Day", days)
+        }
+    }
+}
+
+/// Parses a number of puzzles ("30"), days ("90d") or weeks ("52w").
+impl FromStr for Window {
+    type Err = ParseVariantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let count = |suffix: &str| s.strip_suffix(suffix).and_then(|n| n.parse::<u32>().ok()).filter(|n| *n > 0);
+        if let Some(days) = count("d") {
+            Ok(Window::Days(days))
+        } else if let Some(weeks) = count("w") {
+            Ok(Window::Days(weeks * 7))
+        } else if let Some(puzzles) = count("") {
+            Ok(Window::Puzzles(puzzles))
+        } else {
+            Err(ParseVariantError::new("window", &s))
+        }
+    }
+}
+
+/// Rolls `f` over date-ordered `points`, giving a value at each point from
+/// the points in the window ending there. Points whose window has fewer than
+/// `min_samples` values are skipped, so short series give short results.
+pub fn rolling<F: Fn(&[f64]) -> Option<f64>>(points: &[(NaiveDate, f64)], window: Window, min_samples: usize, f: F) -> Vec<(NaiveDate, f64)> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    for (end, (date, _)) in points.iter().enumerate() {
+        start = match window {
+            Window::Puzzles(count) => (end + 1).saturating_sub(count as usize),
+            Window::Days(days) => {
+                while points[start].0 <= *date - chrono::Duration::days(days as i64) {
+                    start += 1;
                 }
+                start
             }
-            total += curr_time;
-            //println!("{} {} {} {}", xword.print_date.to_string(), last, count, total);
-        } 
-        if count == window {
-            result.push((xword.print_date, total as f64 / window as f64));
+        };
+        let values = points[start..=end].iter().map(|(_, value)| *value).collect::<Vec<_>>();
+        if values.len() < min_samples.max(1) {
+            continue;
+        }
+        if let Some(value) = f(&values) {
+            result.push((*date, value));
         }
     }
+    result
+}
 
+/// Exponentially weighted moving average of date-ordered `points`, where each
+/// point's weight halves every `half_life` puzzles or days. Unlike a window it
+/// reacts to every solve and never drops one abruptly. Values start once
+/// `min_samples` points have been seen.
+pub fn ewma(points: &[(NaiveDate, f64)], half_life: Window, min_samples: usize) -> Vec<(NaiveDate, f64)> {
+    let mut result = Vec::new();
+    let (mut total, mut weight) = (0.0, 0.0);
+    let mut previous: Option<NaiveDate> = None;
+    for (i, (date, value)) in points.iter().enumerate() {
+        let decay = match (half_life, previous) {
+            (_, None) => 1.0,
+            (Window::Puzzles(puzzles), Some(_)) => 0.5f64.powf(1.0 / puzzles as f64),
+            (Window::Days(days), Some(previous)) => 0.5f64.powf((*date - previous).num_days() as f64 / days as f64)
+        };
+        total = total * decay + value;
+        weight = weight * decay + 1.0;
+        previous = Some(*date);
+        if i + 1 >= min_samples {
+            result.push((*date, total / weight));
+        }
+    }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle::{Clue, GridCell};
+    use crate::tracker::SolveSource;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, month, day).unwrap()
+    }
+
+    fn xword(print_date: NaiveDate, solve_state: SolveState) -> XwordSummary {
+        XwordSummary { print_date, puzzle_type: PuzzleType::Daily, solve_state, source: SolveSource::Api }
+    }
+
+    fn gold(print_date: NaiveDate, time: u32) -> XwordSummary {
+        xword(print_date, SolveState::Gold { time })
+    }
+
+    fn mean(values: &[f64]) -> Option<f64> {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+
+    fn assert_close(actual: &[(NaiveDate, f64)], expected: &[(NaiveDate, f64)]) {
+        assert_eq!(actual.len(), expected.len(), "{:?} vs {:?}", actual, expected);
+        for ((date, value), (expected_date, expected_value)) in actual.iter().zip(expected) {
+            assert_eq!(date, expected_date);
+            assert!((value - expected_value).abs() < 1e-9, "{} on {}: expected {}", value, date, expected_value);
+        }
+    }
+
+    // A gap of over a week between the 9th and the 20th.
+    fn points() -> Vec<(NaiveDate, f64)> {
+        vec![(date(3, 1), 1.0), (date(3, 2), 2.0), (date(3, 5), 3.0), (date(3, 9), 4.0), (date(3, 20), 5.0)]
+    }
+
+    #[test]
+    fn tag_summaries_count_each_tagged_puzzle() {
+        let xwords = [gold(date(3, 1), 600), xword(date(3, 2), SolveState::Solved), gold(date(3, 3), 900), xword(date(3, 4), SolveState::Unsolved)];
+        let mut tags = TagMap::new();
+        for (day, tag) in &[(1, "themeless"), (1, "fast"), (2, "themeless"), (3, "themeless"), (4, "rebus")] {
+            tags.entry((date(3, *day), PuzzleType::Daily)).or_default().push(tag.to_string());
+        }
+
+        let summaries = get_tag_summaries(&xwords, &tags);
+        let themeless = &summaries["themeless"];
+        assert_eq!((themeless.count, themeless.solved, themeless.gold, themeless.total_time), (3, 3, 2, 1500));
+        assert_eq!(themeless.mean_time(), Some(750.0));
+        assert_eq!((summaries["fast"].count, summaries["fast"].gold), (1, 1));
+        assert_eq!((summaries["rebus"].count, summaries["rebus"].solved), (1, 0));
+        assert_eq!(summaries["rebus"].mean_time(), None);
+    }
+
+    #[test]
+    fn common_fill_excludes_each_answers_own_puzzle() {
+        // Monday gold times with a median of 10:00. The 22nd has no stored
+        // answers and the 29th has no gold time, so neither gets a point.
+        let xwords = [gold(date(3, 1), 600), gold(date(3, 8), 300), gold(date(3, 15), 900), gold(date(3, 22), 600), xword(date(3, 29), SolveState::Solved)];
+        let answers = [
+            (1, "ERA"), (1, "ORE"), (1, "ASEA"), (1, "ZYZZYVA"),
+            (8, "ERA"), (8, "ORE"), (8, "OBOE"),
+            (15, "ERA"), (15, "ASEA"), (15, "ZEBU"),
+            (29, "ERA")
+        ];
+        let uses = answers.iter().map(|(day, answer)| AnswerUse {
+            print_date: date(3, *day), puzzle_type: PuzzleType::Daily, answer: answer.to_string(), clue: String::new()
+        }).collect::<Vec<_>>();
+
+        let points = |min_count| get_common_fill_points(&xwords, &uses, min_count).into_iter()
+            .map(|p| (p.print_date.day(), p.common_share, p.relative_time))
+            .collect::<Vec<_>>();
+        assert_eq!(points(3), [(1, 0.25, 1.0), (8, 1.0 / 3.0, 0.5), (15, 1.0 / 3.0, 1.5)]);
+        assert_eq!(points(1), [(1, 0.75, 1.0), (8, 2.0 / 3.0, 0.5), (15, 2.0 / 3.0, 1.5)]);
+    }
+
+    // CAT / A#O / BED.
+    fn mini_grid() -> PuzzleGrid {
+        let cells = ["C", "A", "T", "A", "", "O", "B", "E", "D"].iter().map(|letter| GridCell {
+            answer: Some(letter.to_string()).filter(|l| !l.is_empty()), label: None, circled: false, shaded: false
+        }).collect();
+        let clue = |direction, label: &str, cells: &[usize]| Clue { direction, label: label.to_string(), text: String::new(), cells: cells.to_vec() };
+        let clues = vec![
+            clue(Direction::Across, "1", &[0, 1, 2]),
+            clue(Direction::Across, "3", &[6, 7, 8]),
+            clue(Direction::Down, "1", &[0, 3, 6]),
+            clue(Direction::Down, "2", &[2, 5, 8])
+        ];
+        PuzzleGrid { width: 3, height: 3, cells, clues }
+    }
+
+    #[test]
+    fn entry_times_charge_each_pause_to_the_cells_filled_after_it() {
+        // Cells filled together split the pause before them. Eight cells in
+        // 16 seconds is 2 seconds a cell.
+        let times = [(0, 2), (1, 4), (2, 4), (3, 10), (5, 10), (6, 12), (7, 13), (8, 16)];
+        let fills = times.iter().map(|(cell, timestamp)| CellFill { cell: *cell, guess: String::new(), timestamp: *timestamp }).collect::<Vec<_>>();
+
+        let entries = get_entry_times(&mini_grid(), &fills).into_iter()
+            .map(|e| (e.answer, e.region, e.completed, e.span, e.stall, (e.relative_stall * 1000.0).round() / 1000.0))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, [
+            ("CAT".to_string(), "N", 4, 2, 4.0, 0.667),
+            ("BED".to_string(), "S", 16, 4, 6.0, 1.0),
+            ("CAB".to_string(), "W", 12, 10, 7.0, 1.167),
+            ("TOD".to_string(), "E", 16, 12, 7.0, 1.167)
+        ]);
+
+        // Entries missing a cell aren't timed.
+        let answers = get_entry_times(&mini_grid(), &fills[..7]).into_iter().map(|e| e.answer).collect::<Vec<_>>();
+        assert_eq!(answers, ["CAT", "CAB"]);
+    }
+
+    // Fibonacci numbers out of order, summing to 374.
+    const SPREAD: [f64; 10] = [55.0, 2.0, 144.0, 8.0, 3.0, 34.0, 13.0, 89.0, 21.0, 5.0];
+
+    fn assert_near(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("no value");
+        assert!((actual - expected).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn percentile_interpolates_between_ranks() {
+        assert_near(percentile(&SPREAD, 0), 2.0);
+        assert_near(percentile(&SPREAD, 25), 5.75);
+        assert_near(percentile(&SPREAD, 50), 17.0);
+        assert_near(percentile(&SPREAD, 90), 94.5);
+        assert_near(percentile(&SPREAD, 100), 144.0);
+        assert_near(percentile(&SPREAD, 150), 144.0);
+        assert_eq!(percentile(&[], 50), None);
+    }
+
+    #[test]
+    fn trimmed_mean_drops_each_end() {
+        assert_near(trimmed_mean(&SPREAD, 0), 37.4);
+        assert_near(trimmed_mean(&SPREAD, 10), 28.5);
+        assert_near(trimmed_mean(&SPREAD, 20), 136.0 / 6.0);
+        assert_near(trimmed_mean(&SPREAD, 50), 17.0);
+        assert_eq!(trimmed_mean(&[], 10), None);
+    }
+
+    #[test]
+    fn winsorized_mean_clamps_each_end() {
+        assert_near(winsorized_mean(&SPREAD, 0), 37.4);
+        assert_near(winsorized_mean(&SPREAD, 10), 32.0);
+        assert_near(winsorized_mean(&SPREAD, 20), 25.6);
+        assert_eq!(winsorized_mean(&[], 10), None);
+    }
+
+    #[test]
+    fn order_statistics_survive_nan() {
+        let values = [3.0, f64::NAN, 1.0];
+        assert_near(median(&values), 3.0);
+        assert_near(percentile(&values, 0), 1.0);
+        assert!(trimmed_mean(&values, 40).is_some());
+        assert!(winsorized_mean(&values, 40).is_some());
+    }
+
+    #[test]
+    fn silverman_bandwidth_uses_the_smaller_spread() {
+        // The IQR (1.5) is tighter than the standard deviation (2.14)...
+        assert_near(silverman_bandwidth(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]), 0.664677492366943);
+        // ...but an IQR of zero falls back to the standard deviation.
+        assert_near(silverman_bandwidth(&[1.0, 1.0, 1.0, 1.0, 5.0]), 1.166872749618789);
+        assert_eq!(silverman_bandwidth(&[3.0, 3.0, 3.0]), None);
+        assert_eq!(silverman_bandwidth(&[3.0]), None);
+    }
+
+    #[test]
+    fn kernel_density_is_a_normalized_sum_of_gaussians() {
+        assert_near(Some(kernel_density(&[0.0], 1.0, 0.0)), 0.3989422804014327);
+        assert_near(Some(kernel_density(&[0.0], 1.0, 1.0)), 0.24197072451914337);
+        assert_near(Some(kernel_density(&[0.0, 2.0], 1.0, 1.0)), 0.24197072451914337);
+        assert_near(Some(kernel_density(&[0.0], 2.0, 0.0)), 0.3989422804014327 / 2.0);
+
+        let values = [1.0, 2.5, 6.0];
+        let area = (-1000..2000).map(|i| kernel_density(&values, 0.8, i as f64 * 0.01) * 0.01).sum::<f64>();
+        assert!((area - 1.0).abs() < 1e-6, "{}", area);
+    }
+
+    #[test]
+    fn period_reports_group_by_calendar_period_and_weekday() {
+        let xwords = [
+            gold(date(3, 1), 600), xword(date(3, 2), SolveState::Solved), xword(date(3, 3), SolveState::Unsolved),
+            gold(date(3, 8), 300), gold(date(4, 5), 900)
+        ];
+        let summary = |s: &PeriodSummary| (s.count, s.solved, s.gold(), s.mean(), s.median(), s.best());
+
+        let months = get_period_reports(&xwords, Period::Month);
+        assert_eq!(months.keys().collect::<Vec<_>>(), ["2021-03", "2021-04"]);
+        let march = &months["2021-03"];
+        assert_eq!(summary(&march.overall), (4, 3, 2, Some(450.0), Some(450.0), Some(300.0)));
+        assert_eq!(march.overall.gold_rate(), 0.5);
+        assert_eq!(march.by_weekday.keys().collect::<Vec<_>>(), [&0, &1, &2]);
+        assert_eq!(summary(&march.by_weekday[&1]), (1, 1, 0, None, None, None));
+        assert_eq!(summary(&months["2021-04"].overall), (1, 1, 1, Some(900.0), Some(900.0), Some(900.0)));
+
+        let quarters = get_period_reports(&xwords, Period::Quarter);
+        assert_eq!(quarters.iter().map(|(label, r)| (label.as_str(), r.overall.count)).collect::<Vec<_>>(), [("2021-Q1", 4), ("2021-Q2", 1)]);
+        let years = get_period_reports(&xwords, Period::Year);
+        assert_eq!(summary(&years["2021"].overall), (5, 4, 3, Some(600.0), Some(600.0), Some(300.0)));
+    }
+
+    #[test]
+    fn archive_completion_covers_every_daily_in_range() {
+        let day = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let xwords = [
+            XwordSummary { puzzle_type: PuzzleType::Mini, ..gold(day(2020, 12, 30), 40) },
+            gold(day(2020, 12, 31), 900), xword(day(2021, 1, 1), SolveState::Solved),
+            xword(day(2021, 1, 2), SolveState::Unsolved), gold(day(2021, 1, 5), 600)
+        ];
+
+        let archive = get_archive(&xwords, day(2020, 12, 30), day(2021, 1, 3));
+        assert_eq!(archive.values().copied().collect::<Vec<_>>(), [
+            Completion::Untouched, Completion::Gold, Completion::Solved, Completion::Unsolved, Completion::Untouched
+        ]);
+
+        let reports = get_completion_reports(&archive);
+        let summary = |s: &CompletionSummary| (s.puzzles, s.untouched(), s.unsolved, s.solved, s.gold);
+        assert_eq!(summary(&reports[&2020].overall), (2, 1, 0, 1, 1));
+        assert_eq!(reports[&2020].overall.solved_share(), 0.5);
+        assert_eq!(summary(&reports[&2021].overall), (3, 1, 1, 1, 0));
+        assert_eq!(reports[&2021].by_weekday.keys().collect::<Vec<_>>(), [&4, &5, &6]);
+        assert_eq!(summary(&reports[&2021].by_weekday[&5]), (1, 0, 1, 0, 0));
+    }
+
+    #[test]
+    fn rolling_over_puzzles_counts_back_from_each_point() {
+        let expected = [(date(3, 2), 1.5), (date(3, 5), 2.5), (date(3, 9), 3.5), (date(3, 20), 4.5)];
+        assert_close(&rolling(&points(), Window::Puzzles(2), 2, mean), &expected);
+    }
+
+    #[test]
+    fn rolling_over_days_covers_the_days_ending_at_each_point() {
+        // A week ending on the 9th starts on the 3rd, and one ending on the
+        // 20th holds only that day, however many puzzles came before.
+        let expected = [(date(3, 1), 1.0), (date(3, 2), 1.5), (date(3, 5), 2.0), (date(3, 9), 3.5), (date(3, 20), 5.0)];
+        assert_close(&rolling(&points(), Window::Days(7), 1, mean), &expected);
+    }
+
+    #[test]
+    fn rolling_skips_windows_short_of_min_samples() {
+        let expected = [(date(3, 2), 1.5), (date(3, 5), 2.0), (date(3, 9), 3.5)];
+        assert_close(&rolling(&points(), Window::Days(7), 2, mean), &expected);
+    }
+
+    #[test]
+    fn window_parses_puzzles_days_and_weeks() {
+        assert_eq!("30".parse::<Window>().unwrap(), Window::Puzzles(30));
+        assert_eq!("90d".parse::<Window>().unwrap(), Window::Days(90));
+        assert_eq!("52W".parse::<Window>().unwrap(), Window::Days(364));
+        assert!("0".parse::<Window>().is_err());
+        assert!("2m".parse::<Window>().is_err());
+    }
+
+    #[test]
+    fn ewma_halves_weights_every_half_life_puzzles() {
+        // Weights of 1/4, 1/2 and 1 on the last three: (1/4 + 1 + 4) / 1.75.
+        let points = [(date(3, 1), 1.0), (date(3, 2), 2.0), (date(3, 3), 4.0)];
+        let expected = [(date(3, 1), 1.0), (date(3, 2), 2.5 / 1.5), (date(3, 3), 3.0)];
+        assert_close(&ewma(&points, Window::Puzzles(1), 1), &expected);
+    }
+
+    #[test]
+    fn ewma_decays_by_days_elapsed() {
+        // Two days halve the first weight, then four more quarter both.
+        let points = [(date(3, 1), 4.0), (date(3, 3), 8.0), (date(3, 7), 2.0)];
+        let expected = [(date(3, 3), 10.0 / 1.5), (date(3, 7), 4.5 / 1.375)];
+        assert_close(&ewma(&points, Window::Days(2), 2), &expected);
+    }
+
+    // 10:00 on the first day, 10% faster every year since, sampled monthly.
+    fn improving_times() -> Vec<(NaiveDate, f64)> {
+        let start = date(1, 1);
+        (0..40).map(|month| {
+            let date = start + chrono::Duration::days(month * 30);
+            (date, 600.0 * 0.9f64.powf((date - start).num_days() as f64 / 365.25))
+        }).collect()
+    }
+
+    #[test]
+    fn fit_trend_recovers_a_log_linear_slope() {
+        let trend = fit_trend(&improving_times()).unwrap();
+        assert_eq!(trend.count, 40);
+        assert!((trend.slope - 0.9f64.ln()).abs() < 1e-9);
+        assert!((trend.intercept - 600f64.ln()).abs() < 1e-9);
+        assert!((trend.yearly_change() + 0.1).abs() < 1e-9);
+        assert!(trend.slope_error < 1e-9);
+        assert!((trend.at(date(1, 1) + chrono::Duration::days(730)) - 600.0 * 0.9f64.powf(730.0 / 365.25)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn trend_crossing_projects_the_fit() {
+        let trend = fit_trend(&improving_times()).unwrap();
+        assert_eq!(trend.crossing(540.0), Some(date(1, 1) + chrono::Duration::days(365)));
+        assert_eq!(trend.crossing(600.0), Some(date(1, 1)));
+        // Sixty years out is past what the fit can say.
+        assert_eq!(trend.crossing(600.0 * 0.9f64.powi(60)), None);
+    }
+
+    #[test]
+    fn fit_trend_needs_three_solves_on_different_days() {
+        assert!(fit_trend(&improving_times()[..2]).is_none());
+        assert!(fit_trend(&[(date(3, 1), 300.0), (date(3, 1), 400.0), (date(3, 1), 500.0)]).is_none());
+    }
+
+    #[test]
+    fn gold_times_by_solve_date_fall_back_to_print_date() {
+        let xwords = vec![gold(date(1, 4), 300), gold(date(3, 1), 400)];
+        let mut solve_times = SolveTimeMap::new();
+        solve_times.insert((date(1, 4), PuzzleType::Daily), Utc.with_ymd_and_hms(2021, 6, 1, 20, 0, 0).unwrap());
+        let times = get_gold_times_by_solve_date(&xwords, &solve_times, |_| ());
+        assert_eq!(times[&()], vec![(date(3, 1), 400.0), (date(6, 1), 300.0)]);
+    }
+
+    #[test]
+    fn box_summary_fences_at_one_and_a_half_iqrs() {
+        // Quartiles of 3.25 and 7.75 put the fences at -3.5 and 14.5.
+        let summary = box_summary(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 100.0]).unwrap();
+        assert_eq!((summary.low, summary.q1, summary.median, summary.q3, summary.high), (1.0, 3.25, 5.5, 7.75, 9.0));
+        assert_eq!(summary.outliers, vec![100.0]);
+
+        let summary = box_summary(&[-50.0, 10.0, 11.0, 12.0, 13.0]).unwrap();
+        assert_eq!((summary.low, summary.high), (10.0, 13.0));
+        assert_eq!(summary.outliers, vec![-50.0]);
+        assert!(box_summary(&[]).is_none());
+    }
+
+    // Six Mondays, one left open for half an hour, and a slow Saturday.
+    fn mondays() -> Vec<XwordSummary> {
+        let mut xwords = [300, 310, 320, 330, 340, 2000].iter().enumerate()
+            .map(|(week, time)| gold(date(3, 1) + chrono::Duration::weeks(week as i64), *time))
+            .collect::<Vec<_>>();
+        xwords.push(gold(date(3, 6), 2000));
+        xwords.push(xword(date(4, 19), SolveState::Solved));
+        xwords
+    }
+
+    fn detect(rules: OutlierRules) -> Vec<(NaiveDate, String)> {
+        get_outliers(&mondays(), &rules).into_iter().map(|((date, _), reason)| (date, reason)).collect()
+    }
+
+    #[test]
+    fn outliers_by_mad_compare_against_the_weekday_median() {
+        // A median of 325 and a MAD of 15, scaled to 22.24, gives 391.7.
+        let rules = OutlierRules { mad: Some(3.0), ..OutlierRules::default() };
+        assert_eq!(detect(rules), vec![(date(4, 5), "over 6:32 (median + 3 MADs)".to_string())]);
+    }
+
+    #[test]
+    fn outliers_by_iqr_compare_against_the_upper_quartile() {
+        // Quartiles of 312.5 and 337.5 give 337.5 + 1.5 * 25 = 375.
+        let rules = OutlierRules { iqr: Some(1.5), ..OutlierRules::default() };
+        assert_eq!(detect(rules), vec![(date(4, 5), "over 6:15 (Q3 + 1.5 IQRs)".to_string())]);
+    }
+
+    #[test]
+    fn outliers_by_cap_apply_to_their_weekday_first() {
+        let rules = OutlierRules { caps: vec![((PuzzleType::Daily, Weekday::Mon), 330)], mad: Some(3.0), iqr: None };
+        assert_eq!(detect(rules), vec![
+            (date(3, 29), "over 5:30 cap".to_string()),
+            (date(4, 5), "over 5:30 cap".to_string())
+        ]);
+    }
+
+    #[test]
+    fn outliers_by_cap_apply_to_their_puzzle_type() {
+        let mini = |day, time| XwordSummary { puzzle_type: PuzzleType::Mini, ..gold(date(3, day), time) };
+        let xwords = [gold(date(3, 1), 400), mini(1, 400), mini(8, 100)];
+        let detect = |caps| get_outliers(&xwords, &OutlierRules { caps, ..OutlierRules::default() });
+
+        assert_eq!(detect(vec![((PuzzleType::Daily, Weekday::Mon), 330)]), vec![
+            ((date(3, 1), PuzzleType::Daily), "over 5:30 cap".to_string())
+        ]);
+        assert_eq!(detect(vec![((PuzzleType::Mini, Weekday::Mon), 120)]), vec![
+            ((date(3, 1), PuzzleType::Mini), "over 2:00 cap".to_string())
+        ]);
+    }
+
+    #[test]
+    fn caps_default_to_daily_puzzles() {
+        assert_eq!(parse_cap("sat=1:30:00"), Ok(((PuzzleType::Daily, Weekday::Sat), 5400)));
+        assert_eq!(parse_cap("mini:sat=2:00"), Ok(((PuzzleType::Mini, Weekday::Sat), 120)));
+        assert_eq!(parse_cap("Midi : mon=5:00"), Ok(((PuzzleType::Midi, Weekday::Mon), 300)));
+        assert!(parse_cap("maxi:sat=2:00").is_err());
+        assert!(parse_cap("mini:sat").is_err());
+    }
+
+    #[test]
+    fn outliers_need_some_spread() {
+        let xwords = [300, 300, 300, 900].iter().enumerate()
+            .map(|(week, time)| gold(date(3, 1) + chrono::Duration::weeks(week as i64), *time))
+            .collect::<Vec<_>>();
+        assert!(get_outliers(&xwords, &OutlierRules { mad: Some(3.0), ..OutlierRules::default() }).is_empty());
+    }
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2021, 3, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn recency_counts_from_the_next_puzzles_release() {
+        // Monday the 1st's successor comes out at 03:00 UTC on the 2nd, and
+        // the following Monday's at 03:00 UTC on the 8th.
+        let monday = |opened_at, solved_at| Recency::classify(date(3, 1), opened_at, solved_at);
+        assert_eq!(monday(Some(utc(1, 12, 0)), None), Recency::SameDay);
+        assert_eq!(monday(None, Some(utc(2, 2, 59))), Recency::SameDay);
+        assert_eq!(monday(None, Some(utc(2, 3, 0))), Recency::WithinWeek);
+        assert_eq!(monday(Some(utc(1, 20, 0)), Some(utc(5, 0, 0))), Recency::SameDay);
+        assert_eq!(monday(None, Some(utc(8, 2, 59))), Recency::WithinWeek);
+        assert_eq!(monday(None, Some(utc(8, 3, 0))), Recency::Archive);
+        assert_eq!(monday(None, None), Recency::Unknown);
+
+        // Sunday's puzzle comes out at 6pm, 23:00 UTC on Saturday the 6th.
+        assert_eq!(Recency::classify(date(3, 6), None, Some(utc(6, 22, 59))), Recency::SameDay);
+        assert_eq!(Recency::classify(date(3, 6), None, Some(utc(6, 23, 0))), Recency::WithinWeek);
+    }
+
+    #[test]
+    fn solve_clock_buckets_by_local_weekday_and_hour() {
+        // Mondays have a median of 10:00, counting the 15th's, which has no
+        // solve time. New York is UTC-5 until the 14th.
+        let xwords = [
+            gold(date(3, 1), 600), xword(date(3, 2), SolveState::Solved), gold(date(3, 8), 300),
+            xword(date(3, 9), SolveState::Unsolved), gold(date(3, 15), 900)
+        ];
+        let solve_times: SolveTimeMap = vec![
+            (date(3, 1), utc(2, 1, 30)), (date(3, 2), utc(3, 1, 0)), (date(3, 8), utc(9, 4, 0)), (date(3, 9), utc(10, 1, 0))
+        ].into_iter().map(|(print_date, at)| ((print_date, PuzzleType::Daily), at)).collect();
+
+        let clock = get_solve_clock(&xwords, &solve_times, New_York);
+        assert_eq!(clock.counts.into_iter().collect::<Vec<_>>(), [((0, 20), 1), ((0, 23), 1), ((1, 20), 1)]);
+        let summary = |s: &ClockSummary| (s.solves.count, s.solves.gold(), s.relative_times.clone());
+        assert_eq!(summary(&clock.by_hour[&20]), (2, 1, vec![1.0]));
+        assert_eq!(summary(&clock.by_hour[&23]), (1, 1, vec![0.5]));
+        assert_eq!(summary(&clock.by_weekday[&0]), (2, 2, vec![1.0, 0.5]));
+        assert_eq!(clock.by_weekday[&0].median_relative(), Some(0.75));
+
+        let utc_clock = get_solve_clock(&xwords, &solve_times, Tz::UTC);
+        assert_eq!(utc_clock.counts.into_keys().collect::<Vec<_>>(), [(1, 1), (1, 4), (2, 1)]);
+    }
+
+    #[test]
+    fn personal_bests_follow_the_order_they_were_set() {
+        // The archive Monday from February was solved after the 15th came
+        // out, so it beats the 15th rather than the 1st.
+        let mini = XwordSummary { puzzle_type: PuzzleType::Mini, ..gold(date(3, 1), 60) };
+        let xwords = [
+            gold(date(2, 22), 400), gold(date(3, 1), 600), mini, gold(date(3, 8), 700),
+            gold(date(3, 15), 500), xword(date(3, 22), SolveState::Solved)
+        ];
+        let solve_times: SolveTimeMap = vec![((date(2, 22), PuzzleType::Daily), utc(20, 12, 0))].into_iter().collect();
+
+        let bests = get_personal_bests(&xwords, &solve_times).into_iter()
+            .map(|b| (b.print_date, b.puzzle_type, b.time, b.previous, b.solved_at))
+            .collect::<Vec<_>>();
+        assert_eq!(bests, [
+            (date(3, 1), PuzzleType::Daily, 600, None, None),
+            (date(3, 1), PuzzleType::Mini, 60, None, None),
+            (date(3, 15), PuzzleType::Daily, 500, Some(600), None),
+            (date(2, 22), PuzzleType::Daily, 400, Some(500), Some(utc(20, 12, 0)))
+        ]);
+    }
+
+    // Thursday the 4th to Monday the 8th of March 2021, when New York is UTC-5.
+    // Friday's is solved at `friday_solved_at`, the rest well within their day.
+    fn weekend(friday_solved_at: DateTime<Utc>) -> (Vec<XwordSummary>, SolveTimeMap) {
+        let solved_at = [utc(4, 17, 0), friday_solved_at, utc(6, 0, 0), utc(8, 2, 0), utc(8, 17, 0)];
+        let xwords = (4..=8).map(|day| gold(date(3, day), 600)).collect::<Vec<_>>();
+        let solve_times = xwords.iter().zip(&solved_at).map(|(x, at)| ((x.print_date, x.puzzle_type), *at)).collect();
+        (xwords, solve_times)
+    }
+
+    #[test]
+    fn release_time_is_earlier_for_weekend_puzzles() {
+        assert_eq!(release_time(date(3, 5)), utc(5, 3, 0));
+        assert_eq!(release_time(date(3, 6)), utc(5, 23, 0));
+        assert_eq!(release_time(date(3, 8)), utc(8, 3, 0));
+    }
+
+    #[test]
+    fn streak_holds_across_the_weekend_release() {
+        // Friday's solved at 5:30pm, before Saturday's comes out at 6pm, and
+        // Sunday's at 9pm, before Monday's at 10pm.
+        let (xwords, solve_times) = weekend(utc(5, 22, 30));
+        let streaks = get_streaks(&xwords, &solve_times, false, false, utc(8, 20, 0));
+        let current = streaks.current.unwrap();
+        assert_eq!((current.start, current.end, current.length), (date(3, 4), date(3, 8), 5));
+        assert_eq!(streaks.longest.unwrap().length, 5);
+    }
+
+    #[test]
+    fn streak_breaks_on_a_friday_solved_after_6pm() {
+        let (xwords, solve_times) = weekend(utc(5, 23, 30));
+        let streaks = get_streaks(&xwords, &solve_times, false, false, utc(8, 20, 0));
+        let current = streaks.current.unwrap();
+        assert_eq!((current.start, current.length), (date(3, 6), 3));
+        assert_eq!(streaks.history, vec![(date(3, 4), 1), (date(3, 5), 0), (date(3, 6), 1), (date(3, 7), 2), (date(3, 8), 3)]);
+    }
+
+    #[test]
+    fn streak_waits_on_the_open_puzzle() {
+        // Tuesday's is out but unsolved, and there's still time.
+        let (xwords, solve_times) = weekend(utc(5, 22, 30));
+        let streaks = get_streaks(&xwords, &solve_times, false, false, utc(9, 12, 0));
+        assert_eq!(streaks.current.unwrap().length, 5);
+    }
+
+    #[test]
+    fn streak_counts_unknown_solve_times_only_when_lenient() {
+        let (xwords, mut solve_times) = weekend(utc(5, 22, 30));
+        solve_times.remove(&(date(3, 7), PuzzleType::Daily));
+        let strict = get_streaks(&xwords, &solve_times, false, false, utc(8, 20, 0));
+        assert_eq!((strict.current.unwrap().length, strict.longest.unwrap().length, strict.unknown), (1, 3, 1));
+        let lenient = get_streaks(&xwords, &solve_times, false, true, utc(8, 20, 0));
+        assert_eq!(lenient.current.unwrap().length, 5);
+    }
+}
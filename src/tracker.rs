@@ -1,17 +1,40 @@
-use crate::database::{Database, DbError};
-use crate::nytimes::{NYTimes, NYTimesError};
-use crate::stats::{get_daily_moving_averages, get_daily_moving_percentage};
-use crate::util::{date_to_string, string_to_date};
+use crate::chart::{axis_date, axis_date_label, color, plot_archive, plot_box_plots, plot_distributions, plot_metric, plot_solve_clock};
+use crate::database::{Database, DbError, BACKUP_DIR};
+use crate::export::{export_series, export_xwords, ExportError, ExportFormat};
+use crate::filter::XwordFilter;
+use crate::import::{parse_records, plan_import, read_records, ColumnMapping, ConflictPolicy, ImportError, ImportReport};
+use crate::metric::{Metric, PlotOptions, Series};
+use crate::nytimes::{NYTimes, NYTimesError, SyncedXword};
+use crate::puzfile::{write_puzzle, PuzFileError, PuzFileFormat};
+use crate::puzzle::{AnswerUse, ClueMatch, FilledPuzzle, Puzzle};
+use crate::replay::{write_replay, ReplayFormat};
+use crate::stats::{
+    fit_trend, get_archive, get_common_fill_points, get_constructor_summaries, get_entry_times, get_gold_times, get_gold_times_by_solve_date, get_outliers,
+    get_period_reports, get_personal_bests, get_recency_reports, get_solve_clock, get_streaks, get_tag_summaries, CommonFillPoint, Completion, ConstructorSummary,
+    DetectedOutlier, EntryTime, OutlierRules, Period, PeriodReport, PersonalBest, Recency, SolveClock, StreakSummary, TagSummary, Trend
+};
+use crate::util::{date_to_string, format_duration, serialize_date, WEEKDAYS};
 
 use chrono::prelude::*;
+use chrono_tz::Tz;
+use log::info;
 use plotters::prelude::*;
+use serde::Serialize;
 use thiserror::Error;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-static EARLIEST_SOLVE: &str = "2015-06-01";
+// Where syncing starts when there's no last solve, unless config.yaml sets an
+// archive_start. The NYTimes archive itself goes back to 1993-11-21.
+pub static DEFAULT_ARCHIVE_START: &str = "2015-06-01";
 
-#[derive(Debug)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum SolveState {
     Unsolved,
     Solved,
@@ -22,72 +45,409 @@ impl SolveState {
     pub fn from_solved_and_time(solved: bool, time: Option<u32>) -> SolveState {
         if solved {
             match time {
-                Some(time) => SolveState::Gold { time: time },
+                Some(time) => SolveState::Gold { time },
                 None => SolveState::Solved
             }
         } else {
             SolveState::Unsolved
         }
     }
+
+    pub fn to_solved_and_time(self) -> (bool, Option<u32>) {
+        match self {
+            SolveState::Unsolved => (false, None),
+            SolveState::Solved => (true, None),
+            SolveState::Gold { time } => (true, Some(time))
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown {0}: {1}")]
+pub struct ParseVariantError(&'static str, String);
+
+impl ParseVariantError {
+    pub fn new(kind: &'static str, value: &str) -> Self {
+        ParseVariantError(kind, value.to_string())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PuzzleType {
+    #[default]
+    Daily,
+    Mini,
+    Midi,
+    Bonus
+}
+
+impl PuzzleType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PuzzleType::Daily => "daily",
+            PuzzleType::Mini => "mini",
+            PuzzleType::Midi => "midi",
+            PuzzleType::Bonus => "bonus"
+        }
+    }
+}
+
+impl fmt::Display for PuzzleType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PuzzleType {
+    type Err = ParseVariantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "daily" => Ok(PuzzleType::Daily),
+            "mini" => Ok(PuzzleType::Mini),
+            "midi" => Ok(PuzzleType::Midi),
+            "bonus" => Ok(PuzzleType::Bonus),
+            _ => Err(ParseVariantError("puzzle type", s.to_string()))
+        }
+    }
+}
+
+/// Where a row in the database came from. Manual rows are protected from
+/// being overwritten by a sync.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SolveSource {
+    Api,
+    Manual,
+    Import
+}
+
+impl SolveSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SolveSource::Api => "api",
+            SolveSource::Manual => "manual",
+            SolveSource::Import => "import"
+        }
+    }
+}
+
+impl fmt::Display for SolveSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SolveSource {
+    type Err = ParseVariantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "api" => Ok(SolveSource::Api),
+            "manual" => Ok(SolveSource::Manual),
+            "import" => Ok(SolveSource::Import),
+            _ => Err(ParseVariantError("solve source", s.to_string()))
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct XwordSummary {
-    pub print_date: Date<Utc>,
-    pub solve_state: SolveState
+    #[serde(serialize_with = "serialize_date")]
+    pub print_date: NaiveDate,
+    pub puzzle_type: PuzzleType,
+    pub solve_state: SolveState,
+    pub source: SolveSource
+}
+
+/// Which part of a clue to search.
+#[derive(Debug, Clone, Copy)]
+pub enum ClueField {
+    Clue,
+    Answer
 }
 
+#[derive(Serialize, Debug, Clone)]
+pub struct Note {
+    pub id: i64,
+    #[serde(serialize_with = "serialize_date")]
+    pub print_date: NaiveDate,
+    pub puzzle_type: PuzzleType,
+    pub text: String,
+    pub created_at: String
+}
+
+/// Tags attached to each puzzle, keyed by publication date and puzzle type.
+pub type TagMap = HashMap<(NaiveDate, PuzzleType), Vec<String>>;
+
+/// A solve's standing as an outlier, from detection or set by hand.
+#[derive(Debug, Clone)]
+pub struct Outlier {
+    /// False for a manual override keeping a solve detection would flag.
+    pub flagged: bool,
+    /// The rule that flagged it, or "manual".
+    pub reason: String,
+    pub manual: bool
+}
+
+pub type OutlierMap = HashMap<(NaiveDate, PuzzleType), Outlier>;
+
+/// Gold times keyed by year, if broken down by year, and weekday numbered from
+/// Monday.
+pub type GoldTimeMap = BTreeMap<(Option<i32>, u32), Vec<(NaiveDate, f64)>>;
+
+/// When something first happened to each puzzle, keyed by publication date and
+/// puzzle type.
+pub type TimestampMap = HashMap<(NaiveDate, PuzzleType), DateTime<Utc>>;
+
+/// When each puzzle was first solved.
+pub type SolveTimeMap = TimestampMap;
+
 #[derive(Error, Debug)]
 pub enum TrackerError {
     // #[error("Invalid session token provided")]
-    // InvalidSessionError,
+    // InvalidSession,
+
+    #[error(transparent)]
+    NYTimes(#[from] NYTimesError),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+
+    #[error(transparent)]
+    Date(#[from] chrono::ParseError),
+
+    #[error(transparent)]
+    Export(#[from] ExportError),
 
     #[error(transparent)]
-    NYTimesError(#[from] NYTimesError),
+    Import(#[from] ImportError),
 
     #[error(transparent)]
-    DbError(#[from] DbError),
+    PuzFile(#[from] PuzFileError),
 
     #[error(transparent)]
-    DateError(#[from] chrono::ParseError),
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid search: {0}")]
+    InvalidSearch(String),
 
     // // Don't know how to make this compile
     // #[error(transparent)]
-    // PlotError(#[from] DrawingAreaErrorKind<std::error::Error>)
+    // Plot(#[from] DrawingAreaErrorKind<std::error::Error>)
 }
 
 pub struct Tracker {
     db: Database,
-    nytimes: NYTimes
+    nytimes: NYTimes,
+    archive_start: NaiveDate,
+    /// Where we solve, for reading solve timestamps off a local clock.
+    timezone: Tz
 }
 
 impl Tracker {
-    pub fn new(session: String) -> Result<Self, TrackerError> {
+    pub fn new(session: String, archive_start: NaiveDate, timezone: Tz) -> Result<Self, TrackerError> {
         Ok(Tracker{
             db: Database::new("xword.db")?,
-            nytimes: NYTimes::new(session)?
+            nytimes: NYTimes::new(session)?,
+            archive_start,
+            timezone
         })
     }
 
-    pub async fn update_times(&mut self) -> Result<(), TrackerError> {
-        let xwords = self.get_all_xwords().await?;
-        self.db.save_xwords(&xwords)?;
+    /// Syncs solves since the last solve we know of, or since `since` to
+    /// refetch older ones. Returns the puzzles whose details couldn't be
+    /// fetched.
+    pub async fn update_times(&mut self, overwrite_manual: bool, since: Option<NaiveDate>) -> Result<Vec<(u32, NYTimesError)>, TrackerError> {
+        let synced = self.get_all_xwords(since).await?;
+        let ids = synced.iter().map(|x| x.puzzle_id).collect::<Vec<_>>();
+        let mut xwords = Vec::new();
+        let mut fills = Vec::new();
+        let mut open_times = TimestampMap::new();
+        let mut solve_times = SolveTimeMap::new();
+        for x in synced {
+            if let Some(opened_at) = x.opened_at {
+                open_times.insert((x.summary.print_date, x.summary.puzzle_type), opened_at);
+            }
+            if let Some(solved_at) = x.solved_at {
+                solve_times.insert((x.summary.print_date, x.summary.puzzle_type), solved_at);
+            }
+            xwords.push(x.summary);
+            if !x.cell_fills.is_empty() {
+                fills.push((x.puzzle_id, x.cell_fills));
+            }
+        }
+        self.db.save_xwords(&xwords, !overwrite_manual)?;
         self.update_last_solve(&xwords)?;
-        Ok(())
+        let (_, failed) = self.update_puzzles(&ids).await?;
+        self.db.save_cell_fills(&fills)?;
+        self.db.save_open_times(&open_times)?;
+        self.db.save_solve_times(&solve_times)?;
+        Ok(failed)
     }
 
-    async fn get_all_xwords(&self) -> Result<Vec<XwordSummary>, TrackerError> {
-        let start = self.get_last_solve()?;
-        let today = Utc::now().date();
+    async fn get_all_xwords(&self, since: Option<NaiveDate>) -> Result<Vec<SyncedXword>, TrackerError> {
+        let start = match since {
+            Some(since) => since,
+            None => self.get_last_solve()?
+        };
+        let today = Utc::now().date_naive();
 
-        let xwords = self.nytimes.get_all_times(start, today).await?;
+        let synced = self.db.get_synced_game_ids()?;
+        let xwords = self.nytimes.get_all_times(start, today, &synced).await?;
         Ok(xwords)
     }
 
-    fn update_last_solve(&mut self, xwords: &Vec<XwordSummary>) -> Result<(), TrackerError> {
+    /// Fetches metadata, clues and answers for any of the given puzzles we
+    /// don't already have. Returns how many were fetched and how many failed;
+    /// the failures are left for `fetch-puzzles` to retry.
+    async fn update_puzzles(&mut self, ids: &[u32]) -> Result<(usize, Vec<(u32, NYTimesError)>), TrackerError> {
+        let known = self.db.get_puzzle_ids()?;
+        let missing = ids.iter().copied().filter(|id| !known.contains(id)).collect::<Vec<_>>();
+        let fetched = self.nytimes.get_puzzles(&missing).await;
+        self.db.save_puzzles(&fetched.puzzles)?;
+        Ok((fetched.puzzles.len(), fetched.failed))
+    }
+
+    /// Fills in puzzle metadata for a range of dates, e.g. solves synced
+    /// before metadata was tracked. Returns how many puzzles were fetched and
+    /// the ones that failed.
+    pub async fn backfill_puzzles(&mut self, start: Option<NaiveDate>, end: Option<NaiveDate>) -> Result<(usize, Vec<(u32, NYTimesError)>), TrackerError> {
+        let start = start.unwrap_or(self.archive_start);
+        let end = end.unwrap_or_else(|| Utc::now().date_naive());
+        let ids = self.nytimes.get_puzzle_ids(start, end).await?.into_iter().map(|(_, id)| id).collect::<Vec<_>>();
+        self.update_puzzles(&ids).await
+    }
+
+    /// Writes a puzzle file, with our fill, for every solved puzzle matching
+    /// the filter into `dir`, skipping files that already exist unless
+    /// `overwrite` is set. Only stored puzzles are written; returns the files
+    /// written and the number of solves skipped for lack of a stored puzzle.
+    pub fn export_puzzles(&self, filter: &XwordFilter, format: PuzFileFormat, dir: &Path, overwrite: bool) -> Result<(Vec<PathBuf>, usize), TrackerError> {
+        let stored = self.db.get_puzzle_ids()?;
+        let puzzles: HashMap<_, _> = self.db.get_puzzles()?.into_iter()
+            .filter(|p| stored.contains(&p.puzzle_id))
+            .map(|p| ((p.print_date, p.puzzle_type), p))
+            .collect();
+        create_dir_all(dir)?;
+
+        let mut written = Vec::new();
+        let mut missing = 0;
+        for xword in self.load_xwords(filter)? {
+            if let SolveState::Unsolved = xword.solve_state {
+                continue;
+            }
+            let puzzle = match puzzles.get(&(xword.print_date, xword.puzzle_type)) {
+                Some(puzzle) => puzzle,
+                None => {
+                    missing += 1;
+                    continue;
+                }
+            };
+            let path = dir.join(format!("{}-{}.{}", date_to_string(&xword.print_date), xword.puzzle_type, format.extension()));
+            if path.exists() && !overwrite {
+                continue;
+            }
+            let grid = self.db.get_puzzle_grid(puzzle)?;
+            // The puzzle was solved, so any cell without a stored guess held
+            // its answer.
+            let guesses: HashMap<_, _> = self.db.get_cell_fills(puzzle.puzzle_id)?.into_iter().map(|f| (f.cell, f.guess)).collect();
+            let fill = grid.cells.iter().enumerate()
+                .map(|(i, cell)| guesses.get(&i).cloned().or_else(|| cell.answer.clone()).filter(|g| !g.is_empty()))
+                .collect();
+            let filled = FilledPuzzle { puzzle: puzzle.clone(), grid, fill };
+            write_puzzle(&filled, format, BufWriter::new(File::create(&path)?))?;
+            written.push(path);
+        }
+        Ok((written, missing))
+    }
+
+    /// Searches clue text and answers for every word in `query`. A trailing
+    /// `*` on a word matches it as a prefix.
+    pub fn search_clues(&self, query: &str, field: Option<ClueField>, unsolved: bool, limit: u32) -> Result<Vec<ClueMatch>, TrackerError> {
+        let terms = query.split_whitespace().map(|term| {
+            let (term, prefix) = match term.strip_suffix('*') {
+                Some(term) => (term, "*"),
+                None => (term, "")
+            };
+            // An empty quoted string is a syntax error to FTS5, with or
+            // without the prefix star.
+            if term.is_empty() {
+                return Err(TrackerError::InvalidSearch("* needs a word in front of it".to_string()));
+            }
+            Ok(format!("\"{}\"{}", term.replace('"', "\"\""), prefix))
+        }).collect::<Result<Vec<_>, _>>()?;
+        if terms.is_empty() {
+            return Err(TrackerError::InvalidSearch("nothing to search for".to_string()));
+        }
+        let terms = terms.join(" ");
+        let query = match field {
+            Some(ClueField::Clue) => format!("text : ({})", terms),
+            Some(ClueField::Answer) => format!("answer : ({})", terms),
+            None => terms
+        };
+        Ok(self.db.search_clues(&query, unsolved, limit)?)
+    }
+
+    pub fn answers(&self) -> Result<Vec<AnswerUse>, TrackerError> {
+        Ok(self.db.get_answers()?)
+    }
+
+    pub fn common_fill_points(&self, filter: &XwordFilter, min_count: u32) -> Result<Vec<CommonFillPoint>, TrackerError> {
+        let xwords = self.load_xwords(filter)?;
+        Ok(get_common_fill_points(&xwords, &self.db.get_answers()?, min_count))
+    }
+
+    pub fn puzzle(&self, print_date: NaiveDate, puzzle_type: PuzzleType) -> Result<Option<Puzzle>, TrackerError> {
+        Ok(self.db.get_puzzle(print_date, puzzle_type)?)
+    }
+
+    /// Draws a replay of how a puzzle's grid was filled in. Returns None if
+    /// we don't have the puzzle, and no files if we have no fill times for it.
+    pub fn replay(&self, print_date: NaiveDate, puzzle_type: PuzzleType, format: ReplayFormat, output: &Path) -> Result<Option<Vec<PathBuf>>, TrackerError> {
+        let puzzle = match self.db.get_puzzle(print_date, puzzle_type)? {
+            Some(puzzle) => puzzle,
+            None => return Ok(None)
+        };
+        let fills = self.db.get_cell_fills(puzzle.puzzle_id)?;
+        if fills.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+        let grid = self.db.get_puzzle_grid(&puzzle)?;
+        Ok(Some(write_replay(&grid, &fills, format, output)?))
+    }
+
+    /// How long each entry of a puzzle took us. Returns None if we don't have
+    /// the puzzle, and no entries if we have no fill times for it.
+    pub fn stall_report(&self, print_date: NaiveDate, puzzle_type: PuzzleType) -> Result<Option<Vec<EntryTime>>, TrackerError> {
+        let puzzle = match self.db.get_puzzle(print_date, puzzle_type)? {
+            Some(puzzle) => puzzle,
+            None => return Ok(None)
+        };
+        let fills = self.db.get_cell_fills(puzzle.puzzle_id)?;
+        Ok(Some(get_entry_times(&self.db.get_puzzle_grid(&puzzle)?, &fills)))
+    }
+
+    /// Entry times from every puzzle matching the filter that we have fill
+    /// times for.
+    pub fn entry_times(&self, filter: &XwordFilter) -> Result<Vec<EntryTime>, TrackerError> {
+        let xwords = self.load_xwords(filter)?.into_iter().map(|x| (x.print_date, x.puzzle_type)).collect::<HashSet<_>>();
+        let mut entries = Vec::new();
+        for puzzle in self.db.get_filled_puzzles()? {
+            if !xwords.contains(&(puzzle.print_date, puzzle.puzzle_type)) {
+                continue;
+            }
+            let fills = self.db.get_cell_fills(puzzle.puzzle_id)?;
+            entries.extend(get_entry_times(&self.db.get_puzzle_grid(&puzzle)?, &fills));
+        }
+        Ok(entries)
+    }
+
+    fn update_last_solve(&mut self, xwords: &[XwordSummary]) -> Result<(), TrackerError> {
         let latest_solve = xwords.iter().max_by_key(|x| {
             match x.solve_state {
-                SolveState::Unsolved => string_to_date(EARLIEST_SOLVE),
+                SolveState::Unsolved => self.archive_start,
                 SolveState::Solved | SolveState::Gold { .. } => x.print_date
             }
         });
@@ -97,145 +457,450 @@ impl Tracker {
         Ok(())
     }
 
-    fn get_last_solve(&self) -> Result<Date<Utc>, TrackerError> {
+    fn get_last_solve(&self) -> Result<NaiveDate, TrackerError> {
         let last_solve = self.db.get_last_solve()?;
         match last_solve {
             Some(time) => Ok(time),
-            None => Ok(string_to_date(EARLIEST_SOLVE))
+            None => Ok(self.archive_start)
         }
     }
 
-    // moving average of last-N-times
-    // moving average of completion rate
-    // best times 
-    pub fn plot_stats(&self) -> Result<(), TrackerError> {
-        let xwords = self.db.get_xwords()?;
+    fn load_xwords(&self, filter: &XwordFilter) -> Result<Vec<XwordSummary>, TrackerError> {
+        let tags = if filter.uses_tags() { self.db.get_tags()? } else { TagMap::new() };
+        let outliers = if filter.exclude_outliers { self.db.get_outliers()? } else { OutlierMap::new() };
+        Ok(filter.apply(self.db.get_xwords()?, &tags, &outliers))
+    }
 
-        let window = 30;
-        let moving_averages = get_daily_moving_averages(&xwords, window);
-        self.plot_moving_averages(moving_averages, window);
+    /// The flagged outliers that `filter` would match if it didn't exclude them.
+    fn load_flagged(&self, filter: &XwordFilter) -> Result<Vec<XwordSummary>, TrackerError> {
+        let tags = if filter.uses_tags() { self.db.get_tags()? } else { TagMap::new() };
+        let outliers = self.db.get_outliers()?;
+        Ok(self.db.get_xwords()?.into_iter().filter(|xword| {
+            let key = (xword.print_date, xword.puzzle_type);
+            let xword_tags = tags.get(&key).map(|t| t.as_slice()).unwrap_or(&[]);
+            outliers.get(&key).is_some_and(|o| o.flagged) && filter.matches(xword, xword_tags, false)
+        }).collect())
+    }
 
-        let window = 50;
-        let moving_percentages = get_daily_moving_percentage(&xwords, window);
-        self.plot_moving_percentages(moving_percentages, window);
+    pub fn export<W: Write>(&self, filter: &XwordFilter, format: ExportFormat, writer: W) -> Result<(), TrackerError> {
+        let xwords = self.load_xwords(filter)?;
+        export_xwords(&xwords, format, writer)?;
         Ok(())
     }
 
-    fn colors() -> HashMap<Weekday, RGBColor> {
-        let mut colors = HashMap::new();
-        colors.insert(Weekday::Mon, RED);
-        colors.insert(Weekday::Tue, MAGENTA);
-        colors.insert(Weekday::Wed, RGBColor(255, 128, 0)); // Orange
-        colors.insert(Weekday::Thu, RGBColor(0, 128, 0)); // Green
-        colors.insert(Weekday::Fri, RGBColor(0, 128, 255)); //Cyan
-        colors.insert(Weekday::Sat, BLUE);
-        colors.insert(Weekday::Sun, BLACK);
-        colors
+    pub fn export_metric<W: Write>(&self, metric: &dyn Metric, filter: &XwordFilter, format: ExportFormat, writer: W) -> Result<(), TrackerError> {
+        export_series(&self.metric_series(metric, filter)?, format, writer)?;
+        Ok(())
     }
 
-    fn plot_moving_percentages(&self, moving_percentages: HashMap<Weekday, Vec<(Date<Utc>, f64)>>, window: u32) {
-        let colors = Self::colors();
+    pub fn import<R: Read>(&mut self, reader: R, format: ExportFormat, mapping: &ColumnMapping, policy: ConflictPolicy, dry_run: bool) -> Result<ImportReport, TrackerError> {
+        let mut report = ImportReport::default();
+        let incoming = parse_records(read_records(reader, format)?, mapping, &mut report);
+        let to_write = plan_import(&self.db.get_xwords()?, incoming, policy, &mut report);
+        if !dry_run && !to_write.is_empty() {
+            let backup = self.db.backup(Path::new(BACKUP_DIR), Some("pre-import"))?;
+            info!("backed up database to {} before importing", backup.display());
+            self.db.save_xwords(&to_write, false)?;
+        }
+        Ok(report)
+    }
 
-        let root = BitMapBackend::new("graphs/moving_percentages.png", (1024, 768)).into_drawing_area();
+    pub fn backup(&self, dir: Option<&Path>) -> Result<PathBuf, TrackerError> {
+        Ok(self.db.backup(dir.unwrap_or_else(|| Path::new(BACKUP_DIR)), None)?)
+    }
+
+    /// Restores a backup, first backing up the current database so the
+    /// restore itself can be undone.
+    pub fn restore(&mut self, path: &Path) -> Result<PathBuf, TrackerError> {
+        let backup = self.db.backup(Path::new(BACKUP_DIR), Some("pre-restore"))?;
+        self.db.restore(path)?;
+        Ok(backup)
+    }
+
+    pub fn vacuum(&self) -> Result<(), TrackerError> {
+        Ok(self.db.vacuum()?)
+    }
+
+    pub fn integrity_check(&self) -> Result<Vec<String>, TrackerError> {
+        Ok(self.db.integrity_check()?)
+    }
+
+    pub fn record(&mut self, print_date: NaiveDate, puzzle_type: PuzzleType, solve_state: SolveState, notes: Option<&str>) -> Result<(), TrackerError> {
+        let xword = XwordSummary {
+            print_date,
+            puzzle_type,
+            solve_state,
+            source: SolveSource::Manual
+        };
+        self.db.record_xword(&xword, notes)?;
+        Ok(())
+    }
+
+    pub fn add_note(&self, print_date: NaiveDate, puzzle_type: PuzzleType, text: &str) -> Result<i64, TrackerError> {
+        Ok(self.db.add_note(print_date, puzzle_type, text)?)
+    }
+
+    pub fn delete_note(&self, id: i64) -> Result<bool, TrackerError> {
+        Ok(self.db.delete_note(id)?)
+    }
+
+    pub fn notes(&self, print_date: Option<NaiveDate>) -> Result<Vec<Note>, TrackerError> {
+        Ok(self.db.get_notes(print_date)?)
+    }
+
+    pub fn search_notes(&self, query: &str) -> Result<Vec<Note>, TrackerError> {
+        Ok(self.db.search_notes(query)?)
+    }
+
+    pub fn add_tags(&mut self, print_date: NaiveDate, puzzle_type: PuzzleType, tags: &[String]) -> Result<(), TrackerError> {
+        Ok(self.db.add_tags(print_date, puzzle_type, tags)?)
+    }
+
+    pub fn remove_tags(&mut self, print_date: NaiveDate, puzzle_type: PuzzleType, tags: &[String]) -> Result<(), TrackerError> {
+        Ok(self.db.remove_tags(print_date, puzzle_type, tags)?)
+    }
+
+    pub fn tags(&self) -> Result<TagMap, TrackerError> {
+        Ok(self.db.get_tags()?)
+    }
+
+    /// Flags the gold times breaking `rules`, replacing earlier detection but
+    /// not manual overrides. Returns what was flagged.
+    pub fn detect_outliers(&mut self, rules: &OutlierRules, dry_run: bool) -> Result<Vec<DetectedOutlier>, TrackerError> {
+        let manual = self.db.get_outliers()?.into_iter().filter(|(_, o)| o.manual).collect::<OutlierMap>();
+        let outliers = get_outliers(&self.db.get_xwords()?, rules).into_iter()
+            .filter(|(key, _)| !manual.contains_key(key))
+            .collect::<Vec<_>>();
+        if !dry_run {
+            self.db.save_outliers(&outliers)?;
+        }
+        Ok(outliers)
+    }
+
+    /// Marks a solve as an outlier, or as never one, whatever detection says.
+    /// Returns false if there's no solve for the puzzle.
+    pub fn set_outlier(&mut self, print_date: NaiveDate, puzzle_type: PuzzleType, flagged: bool) -> Result<bool, TrackerError> {
+        Ok(self.db.set_outlier(print_date, puzzle_type, flagged)?)
+    }
+
+    pub fn clear_outlier(&mut self, print_date: NaiveDate, puzzle_type: PuzzleType) -> Result<(), TrackerError> {
+        Ok(self.db.clear_outlier(print_date, puzzle_type)?)
+    }
+
+    /// Every flagged or overridden solve, in date order.
+    pub fn outliers(&self) -> Result<Vec<(XwordSummary, Outlier)>, TrackerError> {
+        let mut outliers = self.db.get_outliers()?;
+        Ok(self.db.get_xwords()?.into_iter()
+            .filter_map(|xword| outliers.remove(&(xword.print_date, xword.puzzle_type)).map(|outlier| (xword, outlier)))
+            .collect())
+    }
+
+    pub fn constructor_summaries(&self, filter: &XwordFilter) -> Result<BTreeMap<String, ConstructorSummary>, TrackerError> {
+        let xwords = self.load_xwords(filter)?;
+        Ok(get_constructor_summaries(&xwords, &self.db.get_puzzles()?))
+    }
+
+    /// Gold times for each weekday, and for each year too if `by_year` is set.
+    pub fn gold_times(&self, filter: &XwordFilter, by_year: bool) -> Result<GoldTimeMap, TrackerError> {
+        let xwords = self.load_xwords(filter)?;
+        Ok(get_gold_times(&xwords, |x| {
+            (if by_year { Some(x.print_date.year()) } else { None }, x.print_date.weekday().num_days_from_monday())
+        }))
+    }
+
+    /// Every daily puzzle from the archive start to today, and how far we got
+    /// with it.
+    pub fn archive(&self) -> Result<BTreeMap<NaiveDate, Completion>, TrackerError> {
+        Ok(get_archive(&self.db.get_xwords()?, self.archive_start, Utc::now().date_naive()))
+    }
+
+    pub fn plot_archive(&self, path: &Path) -> Result<(), TrackerError> {
+        plot_archive(&self.archive()?, path);
+        Ok(())
+    }
+
+    pub fn period_reports(&self, filter: &XwordFilter, period: Period) -> Result<BTreeMap<String, PeriodReport>, TrackerError> {
+        Ok(get_period_reports(&self.load_xwords(filter)?, period))
+    }
+
+    pub fn solve_clock(&self, filter: &XwordFilter) -> Result<SolveClock, TrackerError> {
+        Ok(get_solve_clock(&self.load_xwords(filter)?, &self.db.get_solve_times()?, self.timezone))
+    }
+
+    pub fn plot_solve_clock(&self, filter: &XwordFilter, path: &Path) -> Result<(), TrackerError> {
+        plot_solve_clock(&self.solve_clock(filter)?, self.timezone.name(), path);
+        Ok(())
+    }
+
+    pub fn recency_reports(&self, filter: &XwordFilter) -> Result<BTreeMap<Recency, PeriodReport>, TrackerError> {
+        Ok(get_recency_reports(&self.load_xwords(filter)?, &self.db.get_open_times()?, &self.db.get_solve_times()?))
+    }
+
+    pub fn tag_summaries(&self, filter: &XwordFilter) -> Result<BTreeMap<String, TagSummary>, TrackerError> {
+        let xwords = self.load_xwords(filter)?;
+        Ok(get_tag_summaries(&xwords, &self.db.get_tags()?))
+    }
+
+    /// Streaks of daily puzzles solved on the day, counting any solve and
+    /// gold solves only.
+    pub fn streaks(&self, lenient: bool) -> Result<(StreakSummary, StreakSummary), TrackerError> {
+        let xwords = self.db.get_xwords()?;
+        let solve_times = self.db.get_solve_times()?;
+        let now = Utc::now();
+        Ok((
+            get_streaks(&xwords, &solve_times, false, lenient, now),
+            get_streaks(&xwords, &solve_times, true, lenient, now)
+        ))
+    }
+
+    pub fn plot_streaks(&self, all: &StreakSummary, gold: &StreakSummary) {
+        let (start, end) = match (all.history.first(), all.history.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => return
+        };
+        let longest = all.longest.map(|s| s.length).unwrap_or(0).max(1);
+        let series = [("all solves", all, BLUE), ("gold", gold, RGBColor(218, 165, 32))];
+
+        let root = BitMapBackend::new("graphs/streaks.png", (1024, 768)).into_drawing_area();
         root.fill(&WHITE).expect("Failed to fill.");
         let mut chart = ChartBuilder::on(&root)
             .margin(10)
-            .caption(
-                format!("{}-Day Moving Solve Rates by Weekday", window), 
-                ("sans-serif", 40),
-            )
+            .caption("Daily Streaks", ("sans-serif", 40))
             .set_label_area_size(LabelAreaPosition::Left, 60)
             .set_label_area_size(LabelAreaPosition::Bottom, 40)
-            .build_ranged(
-                Utc.ymd(2015, 6, 1)..Utc.ymd(2021, 12, 1),
-                0.0..1.0,
-            ).expect("Failed to draw.");
+            .build_ranged(axis_date(start)..axis_date(end + chrono::Duration::days(1)), 0..longest + longest / 10 + 1)
+            .expect("Failed to draw.");
         chart.configure_mesh()
-            .x_label_formatter(&|d| date_to_string(d))
-            //.y_label_formatter(&|rate| format!("{}:00", *rate as u32))
+            .x_label_formatter(&axis_date_label)
             .draw()
             .expect("Failed to configure mesh.");
-        
-        for (day, data) in moving_percentages.iter() {
-            if data.len() == 0 {
-                continue;
-            }
 
-            let color = colors.get(day).unwrap();
-            chart.draw_series(LineSeries::new(
-                data.iter().map(|(date, f)| (*date, *f)),
-                color,
-            )).expect("Failed to plot.")
-            .label(day.to_string());
+        for (name, summary, color) in &series {
+            chart.draw_series(LineSeries::new(summary.history.iter().map(|(date, length)| (axis_date(*date), *length)), color))
+                .expect("Failed to plot.")
+                .label(*name)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+        }
+        chart.configure_series_labels()
+            .background_style(&WHITE)
+            .border_style(&BLACK)
+            .draw()
+            .expect("Failed to draw legend.");
+    }
 
-            chart.draw_series(PointSeries::of_element(
-                vec![data.last().unwrap().clone()].into_iter(),  
-                1,
-                ShapeStyle::from(color).filled(),
-                &|coord, size, style| {
-                    EmptyElement::at(coord)
-                        + Circle::new((0, 0), size, style)
-                        + Text::new(
-                            format!("{}: {:.2}", day.to_string(), data.last().unwrap().1),
-                            (5, -5),
-                            ("sans-serif", 20).into_font(),
-                        )
-                },
-            )).expect("Failed to draw labels.");
+    /// Recomputes the personal best progression from every solve and stores
+    /// it. Returns the bests that weren't stored before, which is none of
+    /// them the first time. A best is the same one if it's the same time on
+    /// the same puzzle, even if what it beat or when it was solved changed.
+    pub fn update_personal_bests(&mut self) -> Result<Vec<PersonalBest>, TrackerError> {
+        let stored = self.db.get_personal_bests()?.into_iter()
+            .map(|b| (b.print_date, b.puzzle_type, b.time))
+            .collect::<HashSet<_>>();
+        let bests = get_personal_bests(&self.db.get_xwords()?, &self.db.get_solve_times()?);
+        self.db.save_personal_bests(&bests)?;
+        if stored.is_empty() {
+            return Ok(Vec::new());
         }
+        Ok(bests.into_iter().filter(|b| !stored.contains(&(b.print_date, b.puzzle_type, b.time))).collect())
     }
-    
-    fn plot_moving_averages(&self, moving_averages: HashMap<Weekday, Vec<(Date<Utc>, f64)>>, window: u32) {
-        let colors = Self::colors();
 
-        let root = BitMapBackend::new("graphs/moving_averages.png", (1024, 768)).into_drawing_area();
+    pub fn personal_bests(&self) -> Result<Vec<PersonalBest>, TrackerError> {
+        Ok(self.db.get_personal_bests()?)
+    }
+
+    pub fn plot_stats(&self, filter: &XwordFilter, options: &PlotOptions) -> Result<(), TrackerError> {
+        let xwords = self.load_xwords(filter)?;
+
+        let bests = get_personal_bests(&xwords, &self.db.get_solve_times()?);
+        if let Some(last) = bests.iter().map(|b| b.set_at().date_naive()).chain(xwords.iter().map(|x| x.print_date)).max() {
+            self.plot_personal_bests(&bests, last);
+        }
+
+        let by_weekday = get_gold_times(&xwords, |x| x.print_date.weekday().num_days_from_monday());
+        let values = |points: Vec<(NaiveDate, f64)>| points.into_iter().map(|(_, time)| time).collect::<Vec<_>>();
+        plot_distributions(
+            &by_weekday.into_iter().map(|(day, points)| (day, values(points))).collect(),
+            Path::new("graphs/distributions.png")
+        );
+        let mut by_year: BTreeMap<u32, BTreeMap<i32, Vec<f64>>> = BTreeMap::new();
+        for ((day, year), points) in get_gold_times(&xwords, |x| (x.print_date.weekday().num_days_from_monday(), x.print_date.year())) {
+            by_year.entry(day).or_default().insert(year, values(points));
+        }
+        plot_box_plots(&by_year, Path::new("graphs/box_plots.png"));
+
+        let flagged = self.load_flagged(filter)?;
+        for metric in options.metrics() {
+            let path = Path::new("graphs").join(format!("{}.png", metric.file_stem()));
+            plot_metric(metric.as_ref(), &metric.series(&xwords), &metric.trends(&xwords), &metric.flagged(&flagged), &path);
+        }
+        self.plot_solve_clock(filter, Path::new("graphs/time_of_day.png"))?;
+        self.plot_archive(Path::new("graphs/archive.png"))
+    }
+
+    pub fn plot_metric(&self, metric: &dyn Metric, filter: &XwordFilter, path: &Path) -> Result<(), TrackerError> {
+        let xwords = self.load_xwords(filter)?;
+        let flagged = self.load_flagged(filter)?;
+        plot_metric(metric, &metric.series(&xwords), &metric.trends(&xwords), &metric.flagged(&flagged), path);
+        Ok(())
+    }
+
+    pub fn metric_series(&self, metric: &dyn Metric, filter: &XwordFilter) -> Result<Series, TrackerError> {
+        Ok(metric.series(&self.load_xwords(filter)?))
+    }
+
+    /// A log-linear trend through each weekday's gold times, against when
+    /// they were solved.
+    pub fn trends(&self, filter: &XwordFilter) -> Result<BTreeMap<u32, Trend>, TrackerError> {
+        let xwords = self.load_xwords(filter)?;
+        Ok(get_gold_times_by_solve_date(&xwords, &self.db.get_solve_times()?, |x| x.print_date.weekday().num_days_from_monday())
+            .into_iter()
+            .filter_map(|(day, points)| fit_trend(&points).map(|trend| (day, trend)))
+            .collect())
+    }
+
+    fn plot_personal_bests(&self, bests: &[PersonalBest], last: NaiveDate) {
+        let (first, slowest) = match (bests.first(), bests.iter().map(|b| b.time).max()) {
+            (Some(first), Some(slowest)) => (first.set_at().date_naive(), slowest),
+            _ => return
+        };
+
+        let root = BitMapBackend::new("graphs/personal_bests.png", (1024, 768)).into_drawing_area();
         root.fill(&WHITE).expect("Failed to fill.");
         let mut chart = ChartBuilder::on(&root)
             .margin(10)
-            .caption(
-                format!("{}-Day Moving Averages by Weekday", window), 
-                ("sans-serif", 40),
-            )
+            .caption("Personal Bests by Weekday", ("sans-serif", 40))
             .set_label_area_size(LabelAreaPosition::Left, 60)
             .set_label_area_size(LabelAreaPosition::Bottom, 40)
-            .build_ranged(
-                Utc.ymd(2015, 6, 1)..Utc.ymd(2021, 12, 1),
-                0.0..40.0,
-            ).expect("Failed to draw.");
+            .build_ranged(axis_date(first)..axis_date(last + chrono::Duration::days(1)), 0.0..(slowest as f64 / 60.0).ceil() + 1.0)
+            .expect("Failed to draw.");
         chart.configure_mesh()
-            .x_label_formatter(&|d| date_to_string(d))
+            .x_label_formatter(&axis_date_label)
             .y_label_formatter(&|time| format!("{}:00", *time as u32))
             .draw()
             .expect("Failed to configure mesh.");
-        
-        for (day, data) in moving_averages.iter() {
-            if data.len() == 0 {
-                continue;
-            }
 
-            let data_minutes = data.iter().map(|(date, time)| (*date, time / 60.0)).collect::<Vec<_>>();
-
-            let color = colors.get(day).unwrap();
-            chart.draw_series(LineSeries::new(
-                data_minutes.iter().map(|(date, f)| (*date, *f)),
-                color,
-            )).expect("Failed to plot.")
-            .label(day.to_string());
+        for day in &WEEKDAYS {
+            let color = color(day.num_days_from_monday() as i32);
+            let day_bests = bests.iter().filter(|b| b.print_date.weekday() == *day).collect::<Vec<_>>();
+            let current = match day_bests.last() {
+                Some(best) => best.time,
+                None => continue
+            };
+            // A step line: each best holds until the next one.
+            let mut steps = Vec::new();
+            for best in &day_bests {
+                if let Some(previous) = best.previous {
+                    steps.push((axis_date(best.set_at().date_naive()), previous as f64 / 60.0));
+                }
+                steps.push((axis_date(best.set_at().date_naive()), best.time as f64 / 60.0));
+            }
+            steps.push((axis_date(last), current as f64 / 60.0));
 
+            chart.draw_series(LineSeries::new(steps, &color)).expect("Failed to plot.");
             chart.draw_series(PointSeries::of_element(
-                vec![data_minutes.last().unwrap().clone()].into_iter(),  
+                vec![(axis_date(last), current as f64 / 60.0)].into_iter(),
                 1,
-                ShapeStyle::from(color).filled(),
+                ShapeStyle::from(&color).filled(),
                 &|coord, size, style| {
                     EmptyElement::at(coord)
                         + Circle::new((0, 0), size, style)
-                        + Text::new(
-                            format!("{}: {}:{:02}", day.to_string(), data.last().unwrap().1 as u32 / 60, data.last().unwrap().1 as u32 % 60),
-                            (5, -5),
-                            ("sans-serif", 20).into_font(),
-                        )
+                        + Text::new(format!("{}: {}", day, format_duration(current as f64)), (5, -5), ("sans-serif", 20).into_font())
                 },
             )).expect("Failed to draw labels.");
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle::{Clue, Direction, GridCell, PuzzleGrid};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 3, day).unwrap()
+    }
+
+    fn tracker() -> Tracker {
+        Tracker {
+            db: Database::new(":memory:").unwrap(),
+            nytimes: NYTimes::new(String::new()).unwrap(),
+            archive_start: date(1),
+            timezone: chrono_tz::America::New_York
+        }
+    }
+
+    // A 3x3 mini, CAT / A#O / BED, published on the given day.
+    fn save_puzzle(tracker: &mut Tracker, puzzle_id: u32, day: u32, solve_state: SolveState) {
+        let puzzle = Puzzle {
+            puzzle_id, print_date: date(day), puzzle_type: PuzzleType::Mini, title: None, constructors: Vec::new(),
+            editor: None, width: 3, height: 3, has_rebus: false, notes: None
+        };
+        let cells = ["C", "A", "T", "A", "", "O", "B", "E", "D"].iter().map(|letter| GridCell {
+            answer: Some(letter.to_string()).filter(|l| !l.is_empty()), label: None, circled: false, shaded: false
+        }).collect();
+        let clue = |direction, label: &str, text: &str, cells: &[usize]| Clue {
+            direction, label: label.to_string(), text: text.to_string(), cells: cells.to_vec()
+        };
+        let clues = vec![
+            clue(Direction::Across, "1", "Feline friend", &[0, 1, 2]),
+            clue(Direction::Across, "3", "Place to sleep", &[6, 7, 8]),
+            clue(Direction::Down, "1", "Taxi", &[0, 3, 6]),
+            clue(Direction::Down, "2", "Sly \"fox\", in Britain", &[2, 5, 8])
+        ];
+        tracker.db.save_puzzles(&[(puzzle, PuzzleGrid { width: 3, height: 3, cells, clues })]).unwrap();
+        let xword = XwordSummary { print_date: date(day), puzzle_type: PuzzleType::Mini, solve_state, source: SolveSource::Api };
+        tracker.db.save_xwords(&[xword], false).unwrap();
+    }
+
+    fn search(tracker: &Tracker, query: &str, field: Option<ClueField>, unsolved: bool) -> Vec<(NaiveDate, String)> {
+        tracker.search_clues(query, field, unsolved, 10).unwrap().into_iter().map(|m| (m.print_date, m.answer)).collect()
+    }
+
+    #[test]
+    fn search_clues_matches_words_prefixes_and_fields() {
+        let mut tracker = tracker();
+        save_puzzle(&mut tracker, 1, 1, SolveState::Gold { time: 40 });
+
+        assert_eq!(search(&tracker, "FELINE", None, false), [(date(1), "CAT".to_string())]);
+        assert_eq!(search(&tracker, "fel*", None, false), [(date(1), "CAT".to_string())]);
+        assert_eq!(search(&tracker, "feline sleep", None, false), []);
+        assert_eq!(search(&tracker, "\"fox\"", None, false), [(date(1), "TOD".to_string())]);
+        assert_eq!(search(&tracker, "cab", Some(ClueField::Answer), false), [(date(1), "CAB".to_string())]);
+        assert_eq!(search(&tracker, "cab", Some(ClueField::Clue), false), []);
+    }
+
+    #[test]
+    fn search_clues_skips_unsolved_puzzles_unless_asked() {
+        let mut tracker = tracker();
+        save_puzzle(&mut tracker, 1, 1, SolveState::Solved);
+        save_puzzle(&mut tracker, 2, 2, SolveState::Unsolved);
+
+        assert_eq!(search(&tracker, "taxi", None, false), [(date(1), "CAB".to_string())]);
+        assert_eq!(search(&tracker, "taxi", None, true), [(date(2), "CAB".to_string()), (date(1), "CAB".to_string())]);
+    }
+
+    #[test]
+    fn search_clues_rejects_empty_queries() {
+        let tracker = tracker();
+        assert!(matches!(tracker.search_clues("  ", None, false, 10), Err(TrackerError::InvalidSearch(_))));
+        assert!(matches!(tracker.search_clues("cat *", None, false, 10), Err(TrackerError::InvalidSearch(_))));
+    }
+
+    #[test]
+    fn only_new_personal_bests_are_reported() {
+        let mut tracker = tracker();
+        let gold = |day, time| XwordSummary { print_date: date(day), puzzle_type: PuzzleType::Daily, solve_state: SolveState::Gold { time }, source: SolveSource::Api };
+        tracker.db.save_xwords(&[gold(1, 600), gold(15, 500)], false).unwrap();
+        assert!(tracker.update_personal_bests().unwrap().is_empty());
+
+        // Learning when a best was solved doesn't make it new.
+        let solve_times: SolveTimeMap = vec![((date(15), PuzzleType::Daily), Utc.with_ymd_and_hms(2021, 3, 15, 12, 0, 0).unwrap())].into_iter().collect();
+        tracker.db.save_solve_times(&solve_times).unwrap();
+        assert!(tracker.update_personal_bests().unwrap().is_empty());
+
+        tracker.db.save_xwords(&[gold(22, 450)], false).unwrap();
+        let new = tracker.update_personal_bests().unwrap().into_iter().map(|b| (b.print_date, b.time, b.previous)).collect::<Vec<_>>();
+        assert_eq!(new, [(date(22), 450, Some(500))]);
+        assert_eq!(tracker.personal_bests().unwrap().len(), 3);
+    }
+}
@@ -1,10 +1,80 @@
 use chrono::naive::NaiveDate;
 use chrono::prelude::*;
+use serde::Serializer;
 
-pub fn date_to_string(date: &Date<Utc>) -> String {
+pub static WEEKDAYS: [Weekday; 7] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+pub fn date_to_string(date: &NaiveDate) -> String {
     date.format("%Y-%m-%d").to_string()
 }
 
-pub fn string_to_date(s: &str) -> Date<Utc> {
-    Utc.from_utc_date(&NaiveDate::parse_from_str(s, "%Y-%m-%d").expect("Invalid date string"))
+pub fn string_to_date(s: &str) -> NaiveDate {
+    parse_date(s).expect("Invalid date string")
+}
+
+pub fn parse_date(s: &str) -> Result<NaiveDate, chrono::ParseError> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+}
+
+// Accepts plain seconds as well as the m:ss and h:mm:ss a spreadsheet would show.
+pub fn parse_duration(s: &str) -> Result<u32, String> {
+    let invalid = || format!("invalid time {:?}", s);
+    s.split(':').try_fold(0u32, |total, part| {
+        let part = part.trim().parse::<u32>().map_err(|_| invalid())?;
+        total.checked_mul(60).and_then(|t| t.checked_add(part)).ok_or_else(invalid)
+    })
+}
+
+/// Parses a per-weekday time such as "sat=20:00".
+pub fn parse_weekday_duration(s: &str) -> Result<(Weekday, u32), String> {
+    let (day, time) = s.split_once('=').ok_or_else(|| format!("expected <weekday>=<time>, got {:?}", s))?;
+    let day = day.trim().parse::<Weekday>().map_err(|_| format!("invalid weekday {:?}", day))?;
+    Ok((day, parse_duration(time)?))
+}
+
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u32;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+pub fn serialize_date<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&date_to_string(date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_reads_seconds_and_clock_times() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("1:30"), Ok(90));
+        assert_eq!(parse_duration("1:02:03"), Ok(3723));
+        assert_eq!(parse_duration(" 4 : 05 "), Ok(245));
+    }
+
+    #[test]
+    fn parse_duration_rejects_malformed_times() {
+        for s in &["", "1:", "1::30", "1:3o", "-5", "1.5", "4294967296", "71582789:0"] {
+            assert!(parse_duration(s).is_err(), "{:?} should be rejected", s);
+        }
+    }
+
+    #[test]
+    fn parse_weekday_duration_reads_day_and_time() {
+        assert_eq!(parse_weekday_duration("sat=20:00"), Ok((Weekday::Sat, 1200)));
+        assert_eq!(parse_weekday_duration("Monday = 5:30"), Ok((Weekday::Mon, 330)));
+        assert_eq!(parse_weekday_duration("sun=1:00:00"), Ok((Weekday::Sun, 3600)));
+    }
+
+    #[test]
+    fn parse_weekday_duration_rejects_malformed_targets() {
+        for s in &["sat", "sat:20:00", "someday=20:00", "=20:00", "sat=", "sat=twenty"] {
+            assert!(parse_weekday_duration(s).is_err(), "{:?} should be rejected", s);
+        }
+    }
 }